@@ -0,0 +1,559 @@
+//! Comparison of responses with expected values.
+
+use std::future::Future;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::ServiceResponse;
+use actix_web::http::header::{self, HeaderMap};
+use actix_web::http::StatusCode;
+use actix_web::test;
+use actix_web::web::Bytes;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::diff::{diff, diff_with, DiffOptions, Difference, DifferenceKind, ListOrder};
+use crate::incremental::{merge_incremental, MULTIPART_MIXED_MEDIA_TYPE};
+use crate::{
+    BatchExpected, Expected, ExpectedError, GraphQLResponseError, GraphQLResponseReciever,
+    HeaderMatcher, Mismatch, TestFailure, TestOutcome, GRAPHQL_RESPONSE_MEDIA_TYPE,
+};
+
+/// An expectation of the response to a test, which the test framework functions compare with the
+/// response. Implemented by [Expected] for a single GraphQL response, by [BatchExpected] for an
+/// array of responses to a batch of operations, by [crate::IncrementalExpected] for the patches of
+/// an incremental response, by [crate::EventStreamExpected] for the events of a subscription 
+/// served as Server-Sent Events, and by [crate::Snapshot] for a response recorded in a file.
+pub trait Expectation {
+    /// The value returned when the response matches the expectation.
+    type Outcome;
+
+    /// Compares the response with this expectation. Returns the outcome if the response matched,
+    /// or a failure listing every mismatch otherwise.
+    fn check<B: MessageBody>(
+        self,
+        response: ServiceResponse<B>,
+    ) -> impl Future<Output = Result<Self::Outcome, TestFailure>>;
+}
+
+impl<V> Expectation for Expected<V>
+where
+    V: DeserializeOwned + Serialize + PartialEq + std::fmt::Debug,
+{
+    type Outcome = TestOutcome<V>;
+
+    async fn check<B: MessageBody>(self, response: ServiceResponse<B>) -> Result<TestOutcome<V>, TestFailure> {
+        check_response(response, self).await
+    }
+}
+
+impl<V> Expectation for BatchExpected<V>
+where
+    V: DeserializeOwned + Serialize + PartialEq + std::fmt::Debug,
+{
+    type Outcome = Vec<TestOutcome<V>>;
+
+    async fn check<B: MessageBody>(self, response: ServiceResponse<B>) -> Result<Vec<TestOutcome<V>>, TestFailure> {
+        check_batch_response(response, self).await
+    }
+}
+
+/// The parts of a response which are shared by every expectation.
+pub(crate) struct Envelope {
+    pub(crate) status: StatusCode,
+    pub(crate) headers: HeaderMap,
+    pub(crate) media_type: Option<String>,
+    pub(crate) body: Bytes,
+}
+
+/// Reads the response, comparing its status, headers and content type with the expected values.
+/// Returns the envelope of the response, or None if the body could not be read.
+pub(crate) async fn read_envelope<B: MessageBody>(
+    response: ServiceResponse<B>,
+    exp_status: StatusCode,
+    exp_headers: &[(String, HeaderMatcher)],
+    exp_content_type: Option<&str>,
+    mismatches: &mut Vec<Mismatch>,
+) -> Option<Envelope> {
+    // validate status is expected
+    let got_status = response.status();
+    let got_media_type = media_type(&response);
+
+    // validate headers are expected, before the body is consumed
+    let mut header_mismatches = header_mismatches(response.headers(), exp_headers);
+
+    let got_headers = response.headers().clone();
+    let (got_bytes, read_error) = match test::try_read_body(response).await {
+        Ok(b) => (b, None),
+        Err(e) => {
+            let e: Box<dyn std::error::Error> = e.into();
+            (Bytes::new(), Some(e.to_string()))
+        }
+    };
+
+    if got_status != exp_status {
+        mismatches.push(Mismatch::Status {
+            expected: exp_status,
+            got: got_status,
+            body: String::from_utf8_lossy(&got_bytes).into_owned(),
+        });
+    }
+    mismatches.append(&mut header_mismatches);
+
+    if let Some(e) = read_error {
+        mismatches.push(Mismatch::Body(format!("failed to read body: {}", e)));
+        return None;
+    }
+
+    // validate content type is expected
+    if let Some(exp_media_type) = exp_content_type {
+        let matched = got_media_type
+            .as_deref()
+            .is_some_and(|got| got.eq_ignore_ascii_case(essence(exp_media_type)));
+        if !matched {
+            mismatches.push(Mismatch::ContentType {
+                expected: exp_media_type.to_string(),
+                got: got_media_type.clone(),
+            });
+        }
+    }
+
+    Some(Envelope {
+        status: got_status,
+        headers: got_headers,
+        media_type: got_media_type,
+        body: got_bytes,
+    })
+}
+
+/// Compares the headers of a response with the expected header matchers.
+pub(crate) fn header_mismatches(headers: &HeaderMap, exp_headers: &[(String, HeaderMatcher)]) -> Vec<Mismatch> {
+    let mut mismatches = vec![];
+    for (name, matcher) in exp_headers {
+        let values: Vec<&[u8]> = headers.get_all(name.as_str()).map(|v| v.as_bytes()).collect();
+        if !matcher.matches(&values) {
+            mismatches.push(Mismatch::Header {
+                name: name.clone(),
+                expected: matcher.to_string(),
+                got: values.iter().map(|v| String::from_utf8_lossy(v).into_owned()).collect(),
+            });
+        }
+    }
+    mismatches
+}
+
+impl Envelope {
+    pub(crate) fn outcome<V>(&self) -> TestOutcome<V> {
+        TestOutcome {
+            status: self.status,
+            headers: self.headers.clone(),
+            content_type: self.media_type.clone(),
+            data: None,
+            errors: vec![],
+            body: None,
+        }
+    }
+}
+
+/// Compares a response to the expected values, collecting every mismatch.
+pub(crate) async fn check_response<B, V>(
+    response: ServiceResponse<B>,
+    exp: Expected<V>,
+) -> Result<TestOutcome<V>, TestFailure>
+where
+    B: MessageBody,
+    V: DeserializeOwned + Serialize + PartialEq + std::fmt::Debug,
+{
+    let mut mismatches = vec![];
+
+    let envelope = read_envelope(
+        response,
+        exp.status,
+        &exp.headers,
+        exp.content_type.as_deref(),
+        &mut mismatches,
+    )
+    .await;
+    let mut envelope = match envelope {
+        Some(e) => e,
+        None => return Err(TestFailure { mismatches }),
+    };
+
+    // an incremental response is compared as its merged result
+    if envelope.media_type.as_deref() == Some(MULTIPART_MIXED_MEDIA_TYPE) {
+        match merge_incremental(&envelope.headers, &envelope.body) {
+            Ok(merged) => envelope.body = Bytes::from(merged.result.to_string()),
+            Err(e) => {
+                mismatches.push(Mismatch::Body(e));
+                return Err(TestFailure { mismatches });
+            }
+        }
+    }
+
+    let raw: Option<Value> = serde_json::from_slice(&envelope.body).ok();
+
+    // a graphql-response+json body is GraphQL for any status, while the bodies of other media
+    // types are only GraphQL for a non-OK status if they have the shape of a GraphQL response
+    let graphql_body = envelope.status == StatusCode::OK
+        || envelope.media_type.as_deref() == Some(GRAPHQL_RESPONSE_MEDIA_TYPE)
+        || raw.as_ref().is_some_and(is_graphql_body);
+
+    let mut outcome = envelope.outcome();
+
+    // validate error or return, if required
+    if graphql_body {
+        // success case, or error case with a GraphQL body
+        match serde_json::from_slice::<GraphQLResponseReciever<V>>(&envelope.body) {
+            Ok(got) => check_graphql_body(got, raw.as_ref(), exp, &mut outcome, &mut mismatches),
+            Err(e) => mismatches.push(Mismatch::Body(format!(
+                "{}; body: {:?}",
+                e,
+                String::from_utf8_lossy(&envelope.body)
+            ))),
+        }
+    } else {
+        // error case with a plain text body
+        check_text_body(&envelope.body, exp, &mut outcome, &mut mismatches);
+    }
+
+    if mismatches.is_empty() {
+        Ok(outcome)
+    } else {
+        Err(TestFailure { mismatches })
+    }
+}
+
+/// Compares a response holding a JSON array of GraphQL responses to a batch of expected values,
+/// collecting every mismatch.
+async fn check_batch_response<B, V>(
+    response: ServiceResponse<B>,
+    exp: BatchExpected<V>,
+) -> Result<Vec<TestOutcome<V>>, TestFailure>
+where
+    B: MessageBody,
+    V: DeserializeOwned + Serialize + PartialEq + std::fmt::Debug,
+{
+    let mut mismatches = vec![];
+
+    let envelope = read_envelope(
+        response,
+        exp.status,
+        &exp.headers,
+        exp.content_type.as_deref(),
+        &mut mismatches,
+    )
+    .await;
+    let envelope = match envelope {
+        Some(e) => e,
+        None => return Err(TestFailure { mismatches }),
+    };
+
+    let items = match serde_json::from_slice::<Value>(&envelope.body) {
+        Ok(Value::Array(items)) => items,
+        _ => {
+            mismatches.push(Mismatch::Body(format!(
+                "expected a JSON array of GraphQL responses; body: {:?}",
+                String::from_utf8_lossy(&envelope.body)
+            )));
+            return Err(TestFailure { mismatches });
+        }
+    };
+
+    if items.len() != exp.responses.len() {
+        mismatches.push(Mismatch::BatchCount {
+            expected: exp.responses.len(),
+            got: items.len(),
+        });
+    }
+
+    let mut outcomes = vec![];
+    for (index, (item, item_exp)) in items.into_iter().zip(exp.responses).enumerate() {
+        let mut outcome = envelope.outcome();
+        let item_mismatches = check_graphql_value(item, item_exp, &mut outcome);
+
+        if !item_mismatches.is_empty() {
+            mismatches.push(Mismatch::BatchItem {
+                index,
+                mismatches: item_mismatches,
+            });
+        }
+        outcomes.push(outcome);
+    }
+
+    if mismatches.is_empty() {
+        Ok(outcomes)
+    } else {
+        Err(TestFailure { mismatches })
+    }
+}
+
+/// Compares a single GraphQL response as untyped JSON, such as an item of a batch or an event of
+/// a subscription, to the expected errors and data. Returns every mismatch.
+pub(crate) fn check_graphql_value<V>(
+    item: Value,
+    exp: Expected<V>,
+    outcome: &mut TestOutcome<V>,
+) -> Vec<Mismatch>
+where
+    V: DeserializeOwned + Serialize + PartialEq + std::fmt::Debug,
+{
+    let mut mismatches = vec![];
+    match serde_json::from_value::<GraphQLResponseReciever<V>>(item.clone()) {
+        Ok(got) => check_graphql_body(got, Some(&item), exp, outcome, &mut mismatches),
+        Err(e) => mismatches.push(Mismatch::Body(format!("{}; body: {}", e, item))),
+    }
+    mismatches
+}
+
+/// Compares a decoded GraphQL response body to the expected errors and data. `raw` is the same
+/// body as untyped JSON, if it could be parsed.
+fn check_graphql_body<V>(
+    got: GraphQLResponseReciever<V>,
+    raw: Option<&Value>,
+    exp: Expected<V>,
+    outcome: &mut TestOutcome<V>,
+    mismatches: &mut Vec<Mismatch>,
+) where
+    V: Serialize + PartialEq + std::fmt::Debug,
+{
+    let errors = got.errors.unwrap_or_default();
+
+    if let Some(exp_errors) = &exp.errors {
+        mismatches.extend(error_mismatches(&errors, exp_errors));
+    }
+
+    match (exp.data, got.data) {
+        (Some(v), Some(got_data)) => {
+            if exp.list_order == ListOrder::Ordered {
+                if got_data != v {
+                    mismatches.push(Mismatch::Data {
+                        differences: data_differences(&v, &got_data),
+                    });
+                }
+            } else {
+                let opts = DiffOptions {
+                    strict: true,
+                    list_order: exp.list_order.clone(),
+                };
+                let differences = match (serde_json::to_value(&v), serde_json::to_value(&got_data)) {
+                    (Ok(e), Ok(g)) => diff_with(&e, &g, "data", &opts),
+                    _ => data_differences(&v, &got_data),
+                };
+                if !differences.is_empty() {
+                    mismatches.push(Mismatch::Data { differences });
+                }
+            }
+            outcome.data = Some(got_data);
+        }
+        (Some(_), None) => mismatches.push(Mismatch::MissingData {
+            messages: errors.iter().map(|e| e.message.clone()).collect(),
+        }),
+        (None, got_data) => outcome.data = got_data,
+    };
+
+    if let Some(partial) = &exp.partial_data {
+        let opts = DiffOptions {
+            strict: exp.strict,
+            list_order: exp.list_order.clone(),
+        };
+        match raw.and_then(raw_data) {
+            Some(got_data) => {
+                let differences = diff_with(partial, got_data, "data", &opts);
+                if !differences.is_empty() {
+                    mismatches.push(Mismatch::Data { differences });
+                }
+            }
+            None => mismatches.push(Mismatch::MissingData {
+                messages: errors.iter().map(|e| e.message.clone()).collect(),
+            }),
+        }
+    }
+
+    outcome.errors = errors;
+    outcome.body = raw.cloned();
+}
+
+/// Compares a plain text error body to the expected errors. The body is treated as the message of
+/// a single error, with no locations, path or extensions.
+fn check_text_body<V>(
+    got_bytes: &[u8],
+    exp: Expected<V>,
+    outcome: &mut TestOutcome<V>,
+    mismatches: &mut Vec<Mismatch>,
+) {
+    let got_err = match std::str::from_utf8(got_bytes) {
+        Ok(s) => s,
+        Err(e) => {
+            mismatches.push(Mismatch::Body(format!("error body is not valid UTF-8: {}", e)));
+            return;
+        }
+    };
+
+    outcome.errors = vec![GraphQLResponseError {
+        message: got_err.to_string(),
+        locations: None,
+        path: None,
+        extensions: None,
+    }];
+
+    match &exp.errors {
+        Some(exp_errors) => mismatches.extend(error_mismatches(&outcome.errors, exp_errors)),
+        None => {
+            if mismatches.is_empty() {
+                mismatches.push(Mismatch::ErrorBody {
+                    expected: "an error message in Expected::errors, which is required for a plain text body".to_string(),
+                    got: got_err.to_string(),
+                });
+            }
+        }
+    }
+}
+
+/// Returns the media type of the 'Content-Type' header of a response in lower case, without any
+/// parameters. Returns None if the header is absent or not valid UTF-8.
+pub(crate) fn media_type<B>(response: &ServiceResponse<B>) -> Option<String> {
+    let value = response.headers().get(header::CONTENT_TYPE)?.to_str().ok()?;
+    Some(essence(value).to_ascii_lowercase())
+}
+
+/// Returns a media type without its parameters, such as `application/json` for
+/// `application/json; charset=utf-8`.
+fn essence(media_type: &str) -> &str {
+    media_type.split(';').next().unwrap_or_default().trim()
+}
+
+/// Returns true if a response body is a JSON object with a 'data' or 'errors' field, as defined
+/// for a GraphQL response.
+fn is_graphql_body(body: &Value) -> bool {
+    match body {
+        Value::Object(m) => m.contains_key("data") || m.contains_key("errors"),
+        _ => false,
+    }
+}
+
+/// Returns the 'data' field of a JSON response body, or None if it is absent or null.
+fn raw_data(body: &Value) -> Option<&Value> {
+    body.get("data").filter(|d| !d.is_null())
+}
+
+/// Computes the differences between the serialized expected and received data. Falls back to a
+/// single difference of the debug representations if serialization fails, or if the serialized
+/// values are equal despite the data being unequal.
+fn data_differences<V: Serialize + std::fmt::Debug>(exp: &V, got: &V) -> Vec<Difference> {
+    let differences = match (serde_json::to_value(exp), serde_json::to_value(got)) {
+        (Ok(e), Ok(g)) => diff(&e, &g, "data"),
+        _ => vec![],
+    };
+
+    if differences.is_empty() {
+        vec![Difference {
+            path: "data".to_string(),
+            kind: DifferenceKind::Changed {
+                expected: Value::String(format!("{:?}", exp)),
+                got: Value::String(format!("{:?}", got)),
+            },
+        }]
+    } else {
+        differences
+    }
+}
+
+/// Compares the errors from a GraphQL response with the expected errors, in order. Returns a
+/// mismatch for every error that does not match, or a single mismatch if the counts differ.
+fn error_mismatches(got: &[GraphQLResponseError], exp: &[ExpectedError]) -> Vec<Mismatch> {
+    if got.len() != exp.len() {
+        return vec![Mismatch::ErrorCount {
+            expected: exp.len(),
+            got: got.to_vec(),
+        }];
+    }
+
+    got.iter()
+        .zip(exp)
+        .enumerate()
+        .filter_map(|(index, (g, e))| {
+            let details = e.mismatches(g);
+            if details.is_empty() {
+                None
+            } else {
+                Some(Mismatch::Error { index, details })
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+    use actix_web::HttpResponse;
+    use serde_json::json;
+
+    use super::*;
+
+    async fn content_type_mismatches(got: &str, expected: &str) -> Vec<Mismatch> {
+        let response = TestRequest::default()
+            .to_srv_response(HttpResponse::Ok().content_type(got).body("{}"));
+        let mut mismatches = vec![];
+        read_envelope(response, StatusCode::OK, &[], Some(expected), &mut mismatches).await;
+        mismatches
+    }
+
+    async fn batch_mismatches(body: Value, responses: Vec<Expected<Value>>) -> Vec<Mismatch> {
+        let response = TestRequest::default().to_srv_response(HttpResponse::Ok().json(body));
+        match check_batch_response(response, BatchExpected::new(responses)).await {
+            Ok(_) => vec![],
+            Err(failure) => failure.mismatches,
+        }
+    }
+
+    fn data(data: Value) -> Expected<Value> {
+        Expected {
+            data: Some(data),
+            ..Default::default()
+        }
+    }
+
+    #[actix_web::test]
+    async fn batch_compares_each_response_in_order() {
+        let body = json!([{"data": {"a": 1}}, {"data": {"b": 2}}]);
+        assert!(batch_mismatches(body, vec![data(json!({"a": 1})), data(json!({"b": 2}))]).await.is_empty());
+
+        let body = json!([{"data": {"a": 1}}, {"data": {"b": 3}}]);
+        match &batch_mismatches(body, vec![data(json!({"a": 1})), data(json!({"b": 2}))]).await[..] {
+            [Mismatch::BatchItem { index: 1, mismatches }] => {
+                assert!(matches!(mismatches[..], [Mismatch::Data { .. }]))
+            }
+            other => panic!("expected a mismatch of the second response, got {:?}", other),
+        }
+    }
+
+    #[actix_web::test]
+    async fn batch_reports_a_different_number_of_responses() {
+        let body = json!([{"data": {"a": 1}}]);
+        let mismatches = batch_mismatches(body, vec![data(json!({"a": 1})), data(json!({"b": 2}))]).await;
+        assert!(matches!(mismatches[..], [Mismatch::BatchCount { expected: 2, got: 1 }]));
+
+        let body = json!([{"data": {"a": 1}}, {"data": {"b": 2}}, {"data": {"c": 3}}]);
+        let mismatches = batch_mismatches(body, vec![data(json!({"a": 2}))]).await;
+        assert!(matches!(
+            mismatches[..],
+            [Mismatch::BatchCount { expected: 1, got: 3 }, Mismatch::BatchItem { index: 0, .. }]
+        ));
+    }
+
+    #[actix_web::test]
+    async fn batch_requires_an_array_body() {
+        let mismatches = batch_mismatches(json!({"data": {"a": 1}}), vec![data(json!({"a": 1}))]).await;
+        match &mismatches[..] {
+            [Mismatch::Body(reason)] => assert!(reason.starts_with("expected a JSON array of GraphQL responses")),
+            other => panic!("expected a body mismatch, got {:?}", other),
+        }
+    }
+
+    #[actix_web::test]
+    async fn content_type_ignores_parameters_of_expected_and_response() {
+        assert!(content_type_mismatches("application/json", "application/json; charset=utf-8").await.is_empty());
+        assert!(content_type_mismatches("application/json; charset=utf-8", "Application/JSON").await.is_empty());
+        assert_eq!(content_type_mismatches("text/plain", "application/json").await.len(), 1);
+    }
+}
@@ -0,0 +1,323 @@
+//! A structural diff of JSON values, reporting differences by their path in the document.
+
+use serde_json::Value;
+
+use crate::matchers::Matcher;
+
+/// A single difference between an expected and a received JSON value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Difference {
+    /// The path of the differing value, such as `data.user.posts[3].title`.
+    pub path: String,
+    /// How the values differ.
+    pub kind: DifferenceKind,
+}
+
+/// The ways in which two JSON values may differ.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DifferenceKind {
+    /// A value was received, but it was not the expected value.
+    Changed {
+        /// The expected value
+        expected: Value,
+        /// The received value
+        got: Value,
+    },
+    /// An object key or array element was expected but not received.
+    Missing {
+        /// The expected value
+        expected: Value,
+    },
+    /// An object key or array element was received but not expected.
+    Unexpected {
+        /// The received value
+        got: Value,
+    },
+    /// A value was received, but it was not matched by the expected [crate::matchers] matcher.
+    Unmatched {
+        /// A description of the matcher, such as `uuid()`
+        matcher: String,
+        /// The received value
+        got: Value,
+    },
+}
+
+impl std::fmt::Display for Difference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            DifferenceKind::Changed { expected, got } => {
+                write!(f, "{}: expected {}, got {}", self.path, expected, got)
+            }
+            DifferenceKind::Missing { expected } => match Matcher::from_value(expected) {
+                Some(m) => write!(f, "{}: expected {}, got nothing", self.path, m),
+                None => write!(f, "{}: expected {}, got nothing", self.path, expected),
+            },
+            DifferenceKind::Unexpected { got } => write!(f, "{}: unexpected {}", self.path, got),
+            DifferenceKind::Unmatched { matcher, got } => {
+                write!(f, "{}: expected {}, got {}", self.path, matcher, got)
+            }
+        }
+    }
+}
+
+/// Options controlling how [diff_with] compares JSON values.
+#[derive(Debug, Clone)]
+pub struct DiffOptions {
+    /// If true, keys of a received object that are not in the expected object are reported as
+    /// unexpected. If false, only the keys of the expected object are compared, so the expected
+    /// value need only be a subset of the received value. Arrays must always have equal lengths.
+    pub strict: bool,
+    /// Which arrays are compared in order, and which as multisets.
+    pub list_order: ListOrder,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        DiffOptions {
+            strict: true,
+            list_order: ListOrder::Ordered,
+        }
+    }
+}
+
+/// Whether arrays are compared element by element in order, or as multisets in which each 
+/// expected element must match a distinct received element in any position.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ListOrder {
+    /// Every array is compared in order.
+    #[default]
+    Ordered,
+    /// Every array is compared as a multiset.
+    Unordered,
+    /// Arrays at the given paths are compared as multisets, and all others in order. Paths are 
+    /// written as in a [Difference], with `[*]` in place of every array index, such as
+    /// `data.users[*].roles`.
+    UnorderedAt(Vec<String>),
+}
+
+impl ListOrder {
+    fn is_unordered(&self, path: &str) -> bool {
+        match self {
+            ListOrder::Ordered => false,
+            ListOrder::Unordered => true,
+            ListOrder::UnorderedAt(paths) => paths.contains(&wildcard_path(path)),
+        }
+    }
+}
+
+/// Compares two JSON values, returning every difference between them. Any [crate::matchers] in
+/// the expected value are evaluated against the received value in the same position. Paths of differences are
+/// prefixed with `root`. Returns an empty vector if the values are equal.
+pub fn diff(expected: &Value, got: &Value, root: &str) -> Vec<Difference> {
+    diff_with(expected, got, root, &DiffOptions::default())
+}
+
+/// Compares two JSON values according to the options, returning every difference between them. 
+/// Paths of differences are prefixed with `root`. Returns an empty vector if the values match.
+pub fn diff_with(expected: &Value, got: &Value, root: &str, opts: &DiffOptions) -> Vec<Difference> {
+    let mut out = vec![];
+    diff_into(expected, got, root.to_string(), opts, &mut out);
+    out
+}
+
+fn diff_into(expected: &Value, got: &Value, path: String, opts: &DiffOptions, out: &mut Vec<Difference>) {
+    if let Some(m) = Matcher::from_value(expected) {
+        if !m.matches(got) {
+            out.push(Difference {
+                path,
+                kind: DifferenceKind::Unmatched {
+                    matcher: m.to_string(),
+                    got: got.clone(),
+                },
+            });
+        }
+        return;
+    }
+
+    match (expected, got) {
+        (Value::Object(e), Value::Object(g)) => {
+            for (k, ev) in e {
+                let p = field_path(&path, k);
+                match g.get(k) {
+                    Some(gv) => diff_into(ev, gv, p, opts, out),
+                    None => out.push(Difference {
+                        path: p,
+                        kind: DifferenceKind::Missing { expected: ev.clone() },
+                    }),
+                }
+            }
+            if opts.strict {
+                for (k, gv) in g {
+                    if !e.contains_key(k) {
+                        out.push(Difference {
+                            path: field_path(&path, k),
+                            kind: DifferenceKind::Unexpected { got: gv.clone() },
+                        });
+                    }
+                }
+            }
+        }
+        (Value::Array(e), Value::Array(g)) if opts.list_order.is_unordered(&path) => {
+            let compatible: Vec<Vec<bool>> = e
+                .iter()
+                .map(|ev| {
+                    g.iter()
+                        .enumerate()
+                        .map(|(j, gv)| diff_with(ev, gv, &index_path(&path, j), opts).is_empty())
+                        .collect()
+                })
+                .collect();
+
+            // a maximum matching, so that an element matched early never blocks a later one
+            let mut matched_to: Vec<Option<usize>> = vec![None; g.len()];
+            for (i, ev) in e.iter().enumerate() {
+                let mut visited = vec![false; g.len()];
+                if !augment(i, &compatible, &mut matched_to, &mut visited) {
+                    out.push(Difference {
+                        path: index_path(&path, i),
+                        kind: DifferenceKind::Missing { expected: ev.clone() },
+                    });
+                }
+            }
+            for (j, gv) in g.iter().enumerate() {
+                if matched_to[j].is_none() {
+                    out.push(Difference {
+                        path: index_path(&path, j),
+                        kind: DifferenceKind::Unexpected { got: gv.clone() },
+                    });
+                }
+            }
+        }
+        (Value::Array(e), Value::Array(g)) => {
+            for (i, ev) in e.iter().enumerate() {
+                let p = index_path(&path, i);
+                match g.get(i) {
+                    Some(gv) => diff_into(ev, gv, p, opts, out),
+                    None => out.push(Difference {
+                        path: p,
+                        kind: DifferenceKind::Missing { expected: ev.clone() },
+                    }),
+                }
+            }
+            for (i, gv) in g.iter().enumerate().skip(e.len()) {
+                out.push(Difference {
+                    path: index_path(&path, i),
+                    kind: DifferenceKind::Unexpected { got: gv.clone() },
+                });
+            }
+        }
+        (e, g) => {
+            if e != g {
+                out.push(Difference {
+                    path,
+                    kind: DifferenceKind::Changed {
+                        expected: e.clone(),
+                        got: g.clone(),
+                    },
+                });
+            }
+        }
+    }
+}
+
+/// Tries to match an expected element to a received element it is compatible with, moving the
+/// expected elements matched so far to other received elements if needed. Returns true if a match
+/// was found.
+fn augment(
+    i: usize,
+    compatible: &[Vec<bool>],
+    matched_to: &mut [Option<usize>],
+    visited: &mut [bool],
+) -> bool {
+    for j in 0..matched_to.len() {
+        if !compatible[i][j] || visited[j] {
+            continue;
+        }
+        visited[j] = true;
+        let free = match matched_to[j] {
+            None => true,
+            Some(other) => augment(other, compatible, matched_to, visited),
+        };
+        if free {
+            matched_to[j] = Some(i);
+            return true;
+        }
+    }
+    false
+}
+
+pub(crate) fn field_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+pub(crate) fn index_path(path: &str, index: usize) -> String {
+    format!("{}[{}]", path, index)
+}
+
+/// Replaces every array index in a path with `[*]`.
+pub(crate) fn wildcard_path(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    let mut in_index = false;
+    for c in path.chars() {
+        match c {
+            '[' => {
+                in_index = true;
+                out.push_str("[*]");
+            }
+            ']' => in_index = false,
+            _ if in_index => {}
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::matchers;
+
+    fn unordered(strict: bool) -> DiffOptions {
+        DiffOptions {
+            strict,
+            list_order: ListOrder::Unordered,
+        }
+    }
+
+    #[test]
+    fn unordered_subset_finds_matching_when_first_fit_fails() {
+        let expected = json!([{"a": 1}, {"a": 1, "b": 2}]);
+        let got = json!([{"a": 1, "b": 2}, {"a": 1}]);
+        assert_eq!(diff_with(&expected, &got, "data", &unordered(false)), vec![]);
+    }
+
+    #[test]
+    fn unordered_matcher_does_not_take_exact_element() {
+        let expected = json!([matchers::any(), "x"]);
+        let got = json!(["x", "y"]);
+        assert_eq!(diff_with(&expected, &got, "data", &unordered(true)), vec![]);
+    }
+
+    #[test]
+    fn unordered_reports_only_unmatched_elements() {
+        let expected = json!([1, 2, 3]);
+        let got = json!([3, 4, 1]);
+        let differences = diff_with(&expected, &got, "data", &unordered(true));
+        assert_eq!(
+            differences.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            vec!["data[1]: expected 2, got nothing", "data[1]: unexpected 4"]
+        );
+    }
+
+    #[test]
+    fn paths_use_dots_for_fields_and_brackets_for_indexes() {
+        let differences = diff(&json!({"users": [{"id": 1}]}), &json!({"users": [{"id": 2}]}), "data");
+        assert_eq!(differences[0].path, "data.users[0].id");
+    }
+}
@@ -0,0 +1,217 @@
+//! A ready-made executing function, which drives an actix_web `App` directly.
+
+use std::future::Future;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceFactory, ServiceRequest, ServiceResponse};
+use actix_web::http::{header, Method};
+use actix_web::{test, web, App};
+
+use crate::multipart::multipart_body;
+use crate::Argument;
+
+/// An executing function for [crate::test_framework] which builds an app from a factory, registers
+/// the repository with it, and calls it with a request built from an [Argument].
+///
+/// The repository of type `R` is registered with `App::app_data` as `web::Data<R>`, so that
+/// handlers can extract it as `web::Data<R>`. The headers of the argument are added to the
+/// request, and the payload is its body, or its query string for GET.
+#[derive(Debug, Clone)]
+pub struct Executor {
+    /// The path of the GraphQL endpoint. Defaults to `/graphql`.
+    pub path: String,
+    /// The http method of requests whose argument has no method. Defaults to POST.
+    pub method: Method,
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Executor {
+            path: "/graphql".to_string(),
+            method: Method::POST,
+        }
+    }
+}
+
+impl Executor {
+    /// Creates an executor for the endpoint at the path, with the POST method.
+    pub fn new(path: &str) -> Self {
+        Executor {
+            path: path.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Sets the http method of requests.
+    pub fn with_method(mut self, method: Method) -> Self {
+        self.method = method;
+        self
+    }
+
+    /// Builds a test request for the endpoint from an argument, with the method of the argument 
+    /// if it has one. For GET, the payload is sent as URL-encoded query parameters and any
+    /// 'Content-Type' header is omitted. If the argument has files, a multipart POST request is
+    /// built instead, whatever the method. Panics if the payload is not JSON for a multipart
+    /// request, or not a JSON [crate::GraphQLRequest] for GET.
+    pub fn request(&self, arg: &Argument) -> test::TestRequest {
+        let method = arg.method.clone().unwrap_or_else(|| self.method.clone());
+
+        if !arg.files.is_empty() {
+            let (boundary, body) = match multipart_body(&arg.payload, &arg.files) {
+                Ok(b) => b,
+                Err(e) => panic!("Multipart requests require JSON operations as the payload: {}", e),
+            };
+
+            let mut req = test::TestRequest::post().uri(&self.path);
+            for (name, value) in &arg.headers {
+                if !name.eq_ignore_ascii_case("content-type") {
+                    req = req.append_header((name.as_str(), value.as_str()));
+                }
+            }
+            let content_type = format!("multipart/form-data; boundary={}", boundary);
+            return req
+                .insert_header((header::CONTENT_TYPE, content_type))
+                .set_payload(body);
+        }
+
+        if method == Method::GET {
+            let request = match arg.request() {
+                Ok(r) => r,
+                Err(e) => panic!("GET requires a JSON GraphQL request payload: {}", e),
+            };
+            let sep = if self.path.contains('?') { '&' } else { '?' };
+            let uri = format!("{}{}{}", self.path, sep, request.to_query_string());
+
+            let mut req = test::TestRequest::get().uri(&uri);
+            for (name, value) in &arg.headers {
+                if !name.eq_ignore_ascii_case("content-type") {
+                    req = req.append_header((name.as_str(), value.as_str()));
+                }
+            }
+            return req;
+        }
+
+        let mut req = test::TestRequest::default().method(method).uri(&self.path);
+        for (name, value) in &arg.headers {
+            req = req.append_header((name.as_str(), value.as_str()));
+        }
+        req.set_payload(arg.payload.clone())
+    }
+
+    /// Builds the app returned by `app_factory` with the repository registered, and calls it with
+    /// the request built from the argument. Suitable as the body of an executing function, such as
+    /// `|repo, arg| executor.execute(app, repo, arg)`. Panics if the app fails to initialize.
+    pub fn execute<R, F, T, B>(
+        &self,
+        app_factory: F,
+        repo: R,
+        arg: Argument,
+    ) -> impl Future<Output = ServiceResponse<B>>
+    where
+        R: 'static,
+        F: FnOnce() -> App<T>,
+        T: ServiceFactory<
+                ServiceRequest,
+                Config = (),
+                Response = ServiceResponse<B>,
+                Error = actix_web::Error,
+                InitError = (),
+            > + 'static,
+        B: MessageBody,
+    {
+        let app = app_factory().app_data(web::Data::new(repo));
+        let req = self.request(&arg).to_request();
+
+        async move {
+            let service = test::init_service(app).await;
+            test::call_service(&service, req).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{HttpRequest, HttpResponse};
+    use serde::Deserialize;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::{Expectation, Expected, GraphQLRequest};
+
+    /// The query parameters of a GraphQL request sent with GET.
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Params {
+        query: String,
+        operation_name: Option<String>,
+        variables: Option<String>,
+        extensions: Option<String>,
+    }
+
+    /// Responds with the decoded query parameters and 'Content-Type' header of the request, or
+    /// with an empty 405 response to a mutation.
+    async fn graphql_get(req: HttpRequest, params: web::Query<Params>) -> HttpResponse {
+        if params.query.starts_with("mutation") {
+            return HttpResponse::MethodNotAllowed().finish();
+        }
+        let json = |s: &Option<String>| s.as_deref().map(|s| serde_json::from_str::<Value>(s).unwrap());
+        HttpResponse::Ok().json(json!({
+            "query": params.query,
+            "operationName": params.operation_name,
+            "variables": json(&params.variables),
+            "extensions": json(&params.extensions),
+            "contentType": req.headers().get(header::CONTENT_TYPE).map(|v| v.to_str().unwrap()),
+        }))
+    }
+
+    fn app() -> App<
+        impl ServiceFactory<
+            ServiceRequest,
+            Config = (),
+            Response = ServiceResponse<impl MessageBody>,
+            Error = actix_web::Error,
+            InitError = (),
+        >,
+    > {
+        App::new().route("/graphql", web::get().to(graphql_get))
+    }
+
+    #[actix_web::test]
+    async fn get_sends_the_request_as_query_parameters() {
+        let request = GraphQLRequest::new(r#"query Q($a: String) { echo(a: $a, b: "&=?#") }"#)
+            .with_operation_name("Q")
+            .with_variables(json!({"a": "x&y=z é"}))
+            .with_extensions(json!({"k": "ü+%"}));
+        let arg = Argument::new(&request).with_method(Method::GET);
+        assert!(arg.headers.iter().any(|(name, _)| name == "Content-Type"));
+
+        let response = Executor::default().execute(app, (), arg).await;
+        assert_eq!(response.status(), 200);
+        let body: Value = test::read_body_json(response).await;
+        assert_eq!(
+            body,
+            json!({
+                "query": request.query,
+                "operationName": "Q",
+                "variables": {"a": "x&y=z é"},
+                "extensions": {"k": "ü+%"},
+                "contentType": null,
+            })
+        );
+    }
+
+    #[actix_web::test]
+    async fn get_appends_parameters_to_a_path_with_a_query() {
+        let req = Executor::new("/graphql?x=1")
+            .request(&Argument::get(&GraphQLRequest::new("{ a }")))
+            .to_http_request();
+        assert_eq!(req.uri().to_string(), "/graphql?x=1&query=%7B+a+%7D");
+    }
+
+    #[actix_web::test]
+    async fn method_not_allowed_accepts_an_empty_405() {
+        let arg = Argument::get(&GraphQLRequest::new("mutation { a }"));
+        let response = Executor::default().execute(app, (), arg).await;
+        Expected::<Value>::method_not_allowed().check(response).await.unwrap();
+    }
+}
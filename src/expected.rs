@@ -0,0 +1,208 @@
+//! Structures for matching the errors and headers of a GraphQL response against expected values.
+
+use regex::Regex;
+
+use crate::{GraphQLResponseError, Location, PathSegment};
+
+/// A struct for defining an expected GraphQL error. Every field is optional; a field that is None
+/// is not compared, so that a test may pin down an error code or path without depending on the
+/// exact wording of the message.
+#[derive(Debug, Clone, Default)]
+pub struct ExpectedError {
+    /// An optional matcher for the 'message' field of the error.
+    pub message: Option<MessageMatcher>,
+    /// An optional path, compared for equality with the 'path' field of the error.
+    pub path: Option<Vec<PathSegment>>,
+    /// An optional vector of locations, compared for equality with the 'locations' field of the
+    /// error.
+    pub locations: Option<Vec<Location>>,
+    /// An optional error code, compared for equality with the 'code' entry of the 'extensions'
+    /// field of the error.
+    pub code: Option<String>,
+}
+
+impl ExpectedError {
+    /// Creates an expected error that matches any error.
+    pub fn any() -> Self {
+        Default::default()
+    }
+
+    /// Creates an expected error that matches the message exactly and checks nothing else.
+    pub fn message(message: &str) -> Self {
+        ExpectedError {
+            message: Some(MessageMatcher::Exact(message.to_string())),
+            ..Default::default()
+        }
+    }
+
+    /// Creates an expected error that matches the 'code' entry of the extensions and checks
+    /// nothing else.
+    pub fn code(code: &str) -> Self {
+        ExpectedError {
+            code: Some(code.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// Sets the expected message matcher, replacing any that was set before.
+    pub fn with_message(mut self, message: MessageMatcher) -> Self {
+        self.message = Some(message);
+        self
+    }
+
+    /// Sets the expected path, replacing any that was set before.
+    pub fn with_path<P: Into<PathSegment>, I: IntoIterator<Item = P>>(mut self, path: I) -> Self {
+        self.path = Some(path.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Sets the expected locations, replacing any that were set before.
+    pub fn with_locations(mut self, locations: Vec<Location>) -> Self {
+        self.locations = Some(locations);
+        self
+    }
+
+    /// Sets the expected error code, replacing any that was set before.
+    pub fn with_code(mut self, code: &str) -> Self {
+        self.code = Some(code.to_string());
+        self
+    }
+
+    /// Compares this expectation with an error from a GraphQL response. Returns a vector of
+    /// descriptions of every field that did not match; the vector is empty on a match.
+    pub fn mismatches(&self, got: &GraphQLResponseError) -> Vec<String> {
+        let mut out = vec![];
+
+        if let Some(m) = &self.message {
+            if !m.matches(&got.message) {
+                out.push(format!("message: expected {}, got {:?}", m, got.message));
+            }
+        }
+
+        if let Some(p) = &self.path {
+            if got.path.as_ref() != Some(p) {
+                out.push(format!("path: expected {:?}, got {:?}", p, got.path));
+            }
+        }
+
+        if let Some(l) = &self.locations {
+            if got.locations.as_ref() != Some(l) {
+                out.push(format!("locations: expected {:?}, got {:?}", l, got.locations));
+            }
+        }
+
+        if let Some(c) = &self.code {
+            let got_code = got
+                .extensions
+                .as_ref()
+                .and_then(|e| e.get("code"))
+                .and_then(|c| c.as_str());
+            if got_code != Some(c.as_str()) {
+                out.push(format!("extensions.code: expected {:?}, got {:?}", c, got_code));
+            }
+        }
+
+        out
+    }
+
+    /// Returns true if every field of this expectation that is not None matches the error.
+    pub fn matches(&self, got: &GraphQLResponseError) -> bool {
+        self.mismatches(got).is_empty()
+    }
+}
+
+impl From<&str> for ExpectedError {
+    fn from(message: &str) -> Self {
+        ExpectedError::message(message)
+    }
+}
+
+/// A matcher for a GraphQL error message.
+#[derive(Debug, Clone)]
+pub enum MessageMatcher {
+    /// The message must be equal to this string.
+    Exact(String),
+    /// The message must start with this string.
+    Prefix(String),
+    /// The message must contain a match for this regular expression. Use anchors to match the
+    /// whole message.
+    Regex(Regex),
+}
+
+impl MessageMatcher {
+    /// Creates a regular expression matcher. Panics if the pattern is not a valid regular
+    /// expression.
+    pub fn regex(pattern: &str) -> Self {
+        match Regex::new(pattern) {
+            Ok(r) => MessageMatcher::Regex(r),
+            Err(e) => panic!("Invalid regular expression {:?} for message matcher: {}", pattern, e),
+        }
+    }
+
+    /// Returns true if the message is matched.
+    pub fn matches(&self, message: &str) -> bool {
+        match self {
+            MessageMatcher::Exact(s) => message == s,
+            MessageMatcher::Prefix(s) => message.starts_with(s.as_str()),
+            MessageMatcher::Regex(r) => r.is_match(message),
+        }
+    }
+}
+
+impl std::fmt::Display for MessageMatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MessageMatcher::Exact(s) => write!(f, "{:?}", s),
+            MessageMatcher::Prefix(s) => write!(f, "prefix {:?}", s),
+            MessageMatcher::Regex(r) => write!(f, "regex /{}/", r.as_str()),
+        }
+    }
+}
+
+/// A matcher for the values of an http header of a response. A header may appear more than once,
+/// such as 'Set-Cookie'; a matcher of a value succeeds if any of the values match.
+#[derive(Debug, Clone)]
+pub enum HeaderMatcher {
+    /// The header must be present, with any value.
+    Present,
+    /// The header must not be present.
+    Absent,
+    /// The header must have a value equal to this string.
+    Equals(String),
+    /// The header must have a value containing a match for this regular expression.
+    Regex(Regex),
+}
+
+impl HeaderMatcher {
+    /// Creates a regular expression matcher. Panics if the pattern is not a valid regular
+    /// expression.
+    pub fn regex(pattern: &str) -> Self {
+        match Regex::new(pattern) {
+            Ok(r) => HeaderMatcher::Regex(r),
+            Err(e) => panic!("Invalid regular expression {:?} for header matcher: {}", pattern, e),
+        }
+    }
+
+    /// Returns true if the values of a header are matched. Values that are not valid UTF-8 are
+    /// only matched by [HeaderMatcher::Present] and [HeaderMatcher::Absent].
+    pub fn matches(&self, values: &[&[u8]]) -> bool {
+        let mut strs = values.iter().filter_map(|v| std::str::from_utf8(v).ok());
+        match self {
+            HeaderMatcher::Present => !values.is_empty(),
+            HeaderMatcher::Absent => values.is_empty(),
+            HeaderMatcher::Equals(s) => strs.any(|v| v == s),
+            HeaderMatcher::Regex(r) => strs.any(|v| r.is_match(v)),
+        }
+    }
+}
+
+impl std::fmt::Display for HeaderMatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HeaderMatcher::Present => write!(f, "present"),
+            HeaderMatcher::Absent => write!(f, "absent"),
+            HeaderMatcher::Equals(s) => write!(f, "{:?}", s),
+            HeaderMatcher::Regex(r) => write!(f, "regex /{}/", r.as_str()),
+        }
+    }
+}
@@ -0,0 +1,128 @@
+//! Loading of repository seed data from fixture files.
+
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+
+/// Loads seed data from a JSON or YAML fixture file, chosen by the extension of the path: `.json`
+/// for JSON, and `.yaml` or `.yml` for YAML. Relative paths are resolved against the current
+/// directory, which is the package root when run by `cargo test`.
+pub fn load_fixture<D: DeserializeOwned, P: AsRef<Path>>(path: P) -> Result<D, FixtureError> {
+    let path = path.as_ref();
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+
+    let format = match ext.as_deref() {
+        Some("json") => Format::Json,
+        Some("yaml") | Some("yml") => Format::Yaml,
+        _ => return Err(FixtureError::UnknownFormat(path.to_path_buf())),
+    };
+
+    let contents = std::fs::read_to_string(path).map_err(|e| FixtureError::Io(path.to_path_buf(), e))?;
+
+    match format {
+        Format::Json => serde_json::from_str(&contents).map_err(|e| FixtureError::Json(path.to_path_buf(), e)),
+        Format::Yaml => serde_norway::from_str(&contents).map_err(|e| FixtureError::Yaml(path.to_path_buf(), e)),
+    }
+}
+
+enum Format {
+    Json,
+    Yaml,
+}
+
+/// An error loading a fixture file. Each variant holds the path of the file.
+#[derive(Debug)]
+pub enum FixtureError {
+    /// The file could not be read.
+    Io(PathBuf, std::io::Error),
+    /// The file could not be deserialized as JSON.
+    Json(PathBuf, serde_json::Error),
+    /// The file could not be deserialized as YAML.
+    Yaml(PathBuf, serde_norway::Error),
+    /// The extension of the file is not a known format.
+    UnknownFormat(PathBuf),
+}
+
+impl std::fmt::Display for FixtureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FixtureError::Io(p, e) => write!(f, "Failed to read fixture {}: {}", p.display(), e),
+            FixtureError::Json(p, e) => write!(f, "Failed to deserialize JSON fixture {}: {}", p.display(), e),
+            FixtureError::Yaml(p, e) => write!(f, "Failed to deserialize YAML fixture {}: {}", p.display(), e),
+            FixtureError::UnknownFormat(p) => write!(
+                f,
+                "Unknown format of fixture {}; expected a .json, .yaml or .yml extension",
+                p.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FixtureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FixtureError::Io(_, e) => Some(e),
+            FixtureError::Json(_, e) => Some(e),
+            FixtureError::Yaml(_, e) => Some(e),
+            FixtureError::UnknownFormat(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct Seed {
+        users: Vec<String>,
+    }
+
+    /// Writes a fixture file with the name and contents to an empty directory for the test.
+    fn fixture(test: &str, name: &str, contents: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("graphql-actix-test-{}-{}", std::process::id(), test));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn seed() -> Seed {
+        Seed {
+            users: vec!["a".to_string(), "b".to_string()],
+        }
+    }
+
+    #[test]
+    fn loads_json_and_yaml_fixtures() {
+        let json = fixture("json", "seed.json", r#"{"users": ["a", "b"]}"#);
+        assert_eq!(load_fixture::<Seed, _>(&json).unwrap(), seed());
+
+        let yaml = fixture("yaml", "seed.YML", "users:\n  - a\n  - b\n");
+        assert_eq!(load_fixture::<Seed, _>(&yaml).unwrap(), seed());
+
+        let invalid = fixture("invalid", "seed.yaml", "users: {");
+        assert!(matches!(load_fixture::<Seed, _>(&invalid), Err(FixtureError::Yaml(p, _)) if p == invalid));
+
+        for path in [json, yaml, invalid] {
+            std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        }
+    }
+
+    #[test]
+    fn refuses_an_unknown_extension() {
+        let path = fixture("unknown", "seed.toml", "users = []");
+        let err = load_fixture::<Seed, _>(&path).unwrap_err();
+        assert!(matches!(&err, FixtureError::UnknownFormat(p) if p == &path));
+        assert!(err.to_string().contains("expected a .json, .yaml or .yml extension"));
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+
+        assert!(matches!(load_fixture::<Seed, _>("missing.json"), Err(FixtureError::Io(..))));
+    }
+}
@@ -0,0 +1,369 @@
+//! Reading incremental delivery responses to operations with `@defer` or `@stream`, which are sent
+//! as a `multipart/mixed` body of an initial result followed by payloads of patches.
+
+use actix_web::body::MessageBody;
+use actix_web::dev::ServiceResponse;
+use actix_web::http::header::{self, HeaderMap};
+use actix_web::http::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::check::{check_graphql_value, read_envelope};
+use crate::{diff, Expectation, Expected, HeaderMatcher, Mismatch, TestFailure, TestOutcome};
+
+/// The media type of an incremental delivery response body.
+pub const MULTIPART_MIXED_MEDIA_TYPE: &str = "multipart/mixed";
+
+/// A struct for defining the expected output of an operation with `@defer` or `@stream`, whose
+/// response is delivered incrementally as a `multipart/mixed` body.
+///
+/// The patches of every payload after the initial result are applied to it in order: the `data`
+/// of a deferred fragment is merged into the object at its `path`, and the `items` of a stream
+/// are inserted into the list at its `path`, which ends with the index of the first item. Errors
+/// of the patches are added to the errors of the result. The last payload must have `hasNext`
+/// false. Both the payloads of the `incremental` field and the older format of a single patch per
+/// payload are accepted.
+///
+/// An [Expected] also accepts an incremental response, and compares only the merged result.
+pub struct IncrementalExpected<V> {
+    /// An http status code
+    pub status: StatusCode,
+    /// A vector of header names and matchers for the values of the header in the response.
+    pub headers: Vec<(String, HeaderMatcher)>,
+    /// The expected merged result, whose status, headers and content type are ignored.
+    pub result: Expected<V>,
+    /// An optional vector of the expected patches in the order they were delivered, each with
+    /// its `path` and `data` or `items`, and any `label` or `errors`. Compared as JSON; values
+    /// may be [crate::matchers].
+    pub patches: Option<Vec<Value>>,
+}
+
+impl<V> IncrementalExpected<V> {
+    /// Creates an expectation of a 200 OK response with the merged result, which does not check
+    /// the patches.
+    pub fn new(result: Expected<V>) -> Self {
+        IncrementalExpected {
+            status: StatusCode::OK,
+            headers: vec![],
+            result,
+            patches: None,
+        }
+    }
+
+    /// Sets the expected patches.
+    pub fn with_patches(mut self, patches: Vec<Value>) -> Self {
+        self.patches = Some(patches);
+        self
+    }
+}
+
+/// The result of a test of an incremental response which matched every expected value.
+#[derive(Debug)]
+pub struct IncrementalOutcome<V> {
+    /// The merged result. Its `body` is the merged GraphQL response.
+    pub result: TestOutcome<V>,
+    /// Every patch in the order it was delivered.
+    pub patches: Vec<Value>,
+}
+
+impl<V> Expectation for IncrementalExpected<V>
+where
+    V: DeserializeOwned + Serialize + PartialEq + std::fmt::Debug,
+{
+    type Outcome = IncrementalOutcome<V>;
+
+    async fn check<B: MessageBody>(self, response: ServiceResponse<B>) -> Result<IncrementalOutcome<V>, TestFailure> {
+        check_incremental_response(response, self).await
+    }
+}
+
+/// Compares an incremental response with the expected result and patches, collecting every
+/// mismatch.
+async fn check_incremental_response<B, V>(
+    response: ServiceResponse<B>,
+    exp: IncrementalExpected<V>,
+) -> Result<IncrementalOutcome<V>, TestFailure>
+where
+    B: MessageBody,
+    V: DeserializeOwned + Serialize + PartialEq + std::fmt::Debug,
+{
+    let mut mismatches = vec![];
+
+    let envelope = read_envelope(
+        response,
+        exp.status,
+        &exp.headers,
+        Some(MULTIPART_MIXED_MEDIA_TYPE),
+        &mut mismatches,
+    )
+    .await;
+    let envelope = match envelope {
+        Some(e) if e.media_type.as_deref() == Some(MULTIPART_MIXED_MEDIA_TYPE) => e,
+        _ => return Err(TestFailure { mismatches }),
+    };
+
+    let merged = match merge_incremental(&envelope.headers, &envelope.body) {
+        Ok(m) => m,
+        Err(e) => {
+            mismatches.push(Mismatch::Body(e));
+            return Err(TestFailure { mismatches });
+        }
+    };
+
+    let mut outcome = envelope.outcome();
+    mismatches.extend(check_graphql_value(merged.result, exp.result, &mut outcome));
+
+    if let Some(exp_patches) = exp.patches {
+        let differences = diff(&Value::Array(exp_patches), &Value::Array(merged.patches.clone()), "patches");
+        if !differences.is_empty() {
+            mismatches.push(Mismatch::Patches { differences });
+        }
+    }
+
+    if mismatches.is_empty() {
+        Ok(IncrementalOutcome {
+            result: outcome,
+            patches: merged.patches,
+        })
+    } else {
+        Err(TestFailure { mismatches })
+    }
+}
+
+/// An incremental response with its patches applied.
+pub(crate) struct Merged {
+    /// The merged GraphQL response.
+    pub(crate) result: Value,
+    /// Every patch in the order it was delivered.
+    pub(crate) patches: Vec<Value>,
+}
+
+/// Parses a `multipart/mixed` body with the boundary of the 'Content-Type' header, and applies
+/// the patches of every payload to the initial result. Fails with a description if a part is not
+/// JSON, or if the last payload has `hasNext` true.
+pub(crate) fn merge_incremental(headers: &HeaderMap, body: &[u8]) -> Result<Merged, String> {
+    let boundary = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(boundary)
+        .unwrap_or_else(|| "-".to_string());
+
+    let mut payloads = parts(&String::from_utf8_lossy(body), &boundary)
+        .into_iter()
+        .map(|p| serde_json::from_str::<Value>(&p).map_err(|e| format!("{}; part: {:?}", e, p)));
+
+    let mut result = match payloads.next() {
+        Some(p) => p?,
+        None => return Err(format!("no parts with boundary {:?} in incremental response", boundary)),
+    };
+    let mut has_next = result.get("hasNext").and_then(Value::as_bool).unwrap_or(false);
+    if let Some(r) = result.as_object_mut() {
+        r.remove("hasNext");
+    }
+
+    let mut patches = vec![];
+    for payload in payloads {
+        let payload = payload?;
+        has_next = payload.get("hasNext").and_then(Value::as_bool).unwrap_or(false);
+
+        let items = match payload.get("incremental") {
+            Some(Value::Array(items)) => items.clone(),
+            Some(_) => return Err(format!("'incremental' is not an array in payload {}", payload)),
+            None if payload.get("path").is_some() => {
+                let mut item = payload.clone();
+                if let Some(i) = item.as_object_mut() {
+                    i.remove("hasNext");
+                }
+                vec![item]
+            }
+            None => vec![],
+        };
+        for item in items {
+            apply(&mut result, &item)?;
+            patches.push(item);
+        }
+
+        // the errors of a payload in the older format belong to its single patch
+        if payload.get("incremental").is_some() || payload.get("path").is_none() {
+            if let Some(Value::Array(errors)) = payload.get("errors") {
+                push_errors(&mut result, errors);
+            }
+        }
+    }
+
+    if has_next {
+        return Err("incremental response ended with 'hasNext' true".to_string());
+    }
+
+    Ok(Merged { result, patches })
+}
+
+/// Returns the boundary parameter of a 'Content-Type' header value.
+fn boundary(content_type: &str) -> Option<String> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        if name.trim().eq_ignore_ascii_case("boundary") {
+            Some(value.trim().trim_matches('"').to_string())
+        } else {
+            None
+        }
+    })
+}
+
+/// Splits a multipart body into the bodies of its parts, skipping the headers of each part and
+/// any empty part.
+fn parts(body: &str, boundary: &str) -> Vec<String> {
+    let delimiter = format!("--{}", boundary);
+    let closing = format!("--{}--", boundary);
+
+    let mut parts = vec![];
+    let mut current: Option<Vec<&str>> = None;
+    for line in body.split('\n') {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if line == delimiter || line == closing {
+            if let Some(lines) = current.take() {
+                parts.push(part_body(&lines));
+            }
+            if line == closing {
+                break;
+            }
+            current = Some(vec![]);
+        } else if let Some(lines) = current.as_mut() {
+            lines.push(line);
+        }
+    }
+    if let Some(lines) = current {
+        parts.push(part_body(&lines));
+    }
+
+    parts.retain(|p| !p.trim().is_empty());
+    parts
+}
+
+fn part_body(lines: &[&str]) -> String {
+    let start = lines.iter().position(|l| l.is_empty()).map(|i| i + 1).unwrap_or(0);
+    lines[start..].join("\n")
+}
+
+/// Applies a deferred or streamed patch to the result. A deferred patch with null data, which
+/// failed with errors, leaves the result unchanged.
+fn apply(result: &mut Value, item: &Value) -> Result<(), String> {
+    let path = match item.get("path") {
+        Some(Value::Array(p)) => p.clone(),
+        _ => return Err(format!("patch has no path: {}", item)),
+    };
+
+    if let Some(Value::Array(errors)) = item.get("errors") {
+        push_errors(result, errors);
+    }
+
+    if let Some(Value::Array(items)) = item.get("items") {
+        let (index, list_path) = match path.split_last() {
+            Some((Value::Number(n), rest)) => (n.as_u64().unwrap_or_default() as usize, rest),
+            _ => return Err(format!("stream patch path does not end with an index: {}", item)),
+        };
+        let list = match target(result, list_path) {
+            Some(Value::Array(list)) => list,
+            _ => return Err(format!("no list at the path of stream patch {}", item)),
+        };
+        for (i, v) in items.iter().enumerate() {
+            if list.len() <= index + i {
+                list.resize(index + i + 1, Value::Null);
+            }
+            list[index + i] = v.clone();
+        }
+    } else if let Some(data) = item.get("data").filter(|d| !d.is_null()) {
+        match target(result, &path) {
+            Some(t) => merge(t, data),
+            None => return Err(format!("no value at the path of defer patch {}", item)),
+        }
+    }
+    Ok(())
+}
+
+/// Returns the value at a path of the data of the result.
+fn target<'a>(result: &'a mut Value, path: &[Value]) -> Option<&'a mut Value> {
+    path.iter().try_fold(result.get_mut("data")?, |cur, seg| match seg {
+        Value::String(s) => cur.get_mut(s.as_str()),
+        Value::Number(n) => cur.get_mut(n.as_u64()? as usize),
+        _ => None,
+    })
+}
+
+/// Merges the fields of an object into another, recursively. Any other value replaces the
+/// target.
+fn merge(target: &mut Value, patch: &Value) {
+    match (target, patch) {
+        (Value::Object(t), Value::Object(p)) => {
+            for (k, v) in p {
+                match t.get_mut(k) {
+                    Some(existing) => merge(existing, v),
+                    None => {
+                        t.insert(k.clone(), v.clone());
+                    }
+                }
+            }
+        }
+        (t, p) => *t = p.clone(),
+    }
+}
+
+fn push_errors(result: &mut Value, errors: &[Value]) {
+    if let Some(r) = result.as_object_mut() {
+        match r.entry("errors").or_insert_with(|| json!([])) {
+            Value::Array(existing) => existing.extend(errors.iter().cloned()),
+            other => *other = Value::Array(errors.to_vec()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::header::HeaderValue;
+
+    use super::*;
+
+    fn merge_body(content_type: &str, body: &str) -> Result<Merged, String> {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_str(content_type).unwrap());
+        merge_incremental(&headers, body.as_bytes())
+    }
+
+    #[test]
+    fn merges_parts_with_a_quoted_boundary() {
+        let body = "\r\n---\r\nContent-Type: application/json\r\n\r\n\
+            {\"data\":{\"user\":{\"id\":1}},\"hasNext\":true}\r\n---\r\n\
+            Content-Type: application/json\r\n\r\n\
+            {\"incremental\":[{\"path\":[\"user\"],\"data\":{\"name\":\"a\"}}],\"hasNext\":false}\r\n-----\r\n";
+        let merged = merge_body("multipart/mixed; boundary=\"-\"", body).unwrap();
+        assert_eq!(merged.result, json!({"data": {"user": {"id": 1, "name": "a"}}}));
+        assert_eq!(merged.patches, [json!({"path": ["user"], "data": {"name": "a"}})]);
+    }
+
+    #[test]
+    fn merges_the_older_single_patch_format() {
+        let body = "--x\n\n{\"data\":{\"user\":{}},\"hasNext\":true}\n--x\n\n\
+            {\"path\":[\"user\"],\"label\":\"l\",\"data\":null,\"errors\":[{\"message\":\"e\"}],\"hasNext\":false}\n--x--\n";
+        let merged = merge_body("multipart/mixed; boundary=x", body).unwrap();
+        assert_eq!(merged.result, json!({"data": {"user": {}}, "errors": [{"message": "e"}]}));
+        assert_eq!(merged.patches, [json!({"path": ["user"], "label": "l", "data": null, "errors": [{"message": "e"}]})]);
+    }
+
+    #[test]
+    fn streams_items_into_a_list() {
+        let body = "---\n\n{\"data\":{\"users\":[1]},\"hasNext\":true}\n---\n\n\
+            {\"incremental\":[{\"path\":[\"users\",1],\"items\":[2,3]}],\"hasNext\":true}\n---\n\n\
+            {\"incremental\":[{\"path\":[\"users\",3],\"items\":[4]}],\"hasNext\":false}\n-----\n";
+        let merged = merge_body("multipart/mixed", body).unwrap();
+        assert_eq!(merged.result, json!({"data": {"users": [1, 2, 3, 4]}}));
+        assert_eq!(merged.patches.len(), 2);
+    }
+
+    #[test]
+    fn fails_if_the_last_payload_has_next() {
+        let body = "---\n\n{\"data\":{},\"hasNext\":true}\n---\n\n{\"incremental\":[],\"hasNext\":true}\n-----\n";
+        let err = merge_body("multipart/mixed; boundary=\"-\"", body).err().unwrap();
+        assert_eq!(err, "incremental response ended with 'hasNext' true");
+    }
+}
@@ -0,0 +1,173 @@
+//! Initialization that runs only once per process, shared by all tests in a test binary.
+//!
+//! Tests run in parallel threads of the same process, so setup such as installing a logger or
+//! loading environment variables would otherwise be duplicated, or raced, by every test. Each
+//! initializer runs at most once successfully; concurrent callers wait for the first to finish,
+//! and if an initializer panics, the next caller runs it again.
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// The identity of an initializer: its type, the address of a function pointer, or a key given by
+/// the caller.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum InitKey {
+    Type(TypeId),
+    Pointer(usize),
+    Name(String),
+}
+
+type Registry<C> = OnceLock<Mutex<HashMap<InitKey, Arc<C>>>>;
+
+static SYNC_CELLS: Registry<OnceLock<()>> = OnceLock::new();
+static ASYNC_CELLS: Registry<tokio::sync::OnceCell<()>> = OnceLock::new();
+
+/// Returns the cell for a key, creating it if this is the first use of the key.
+fn cell<C: Default>(registry: &Registry<C>, key: InitKey) -> Arc<C> {
+    let mut cells = registry
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    cells.entry(key).or_default().clone()
+}
+
+/// Returns the key of an initializer that is not a function pointer: its type, if it is a function
+/// or closure expression, whose type belongs to it alone. Panics for any other type, such as a
+/// boxed closure, whose type may be shared by different initializers.
+fn type_key<F: 'static>(per_key: &str) -> InitKey {
+    // a function item has a type of size zero; a closure may capture values, but is named after
+    // the expression that defines it
+    if std::mem::size_of::<F>() == 0 || std::any::type_name::<F>().contains("{{closure}}") {
+        InitKey::Type(TypeId::of::<F>())
+    } else {
+        panic!(
+            "Cannot tell initializers of type {} apart; pass them to {} with a key for each",
+            std::any::type_name::<F>(),
+            per_key
+        )
+    }
+}
+
+/// Runs the initializer only once per process. A function or closure expression is identified by
+/// its type, so every call with the same one shares one run, and a function pointer by its
+/// address. Panics for an initializer of any other type, such as a `Box<dyn Fn()>`, which should
+/// be passed to [init_once_per_key] instead.
+pub fn init_once<F: FnOnce() + 'static>(f: F) {
+    let key = match (&f as &dyn Any).downcast_ref::<fn()>() {
+        Some(p) => InitKey::Pointer(*p as usize),
+        None => type_key::<F>("init_once_per_key"),
+    };
+    cell(&SYNC_CELLS, key).get_or_init(f);
+}
+
+/// Runs the initializer only once per process for each key, regardless of which initializer is
+/// passed with the key.
+pub fn init_once_per_key<F: FnOnce()>(key: &str, f: F) {
+    cell(&SYNC_CELLS, InitKey::Name(key.to_string())).get_or_init(f);
+}
+
+/// Runs the async initializer only once per process. Initializers are identified as by
+/// [init_once], so a function pointer such as a `fn() -> Pin<Box<dyn Future<Output = ()>>>` is
+/// identified by its address. Panics for an initializer of any other type, which should be passed
+/// to [init_once_per_key_async] instead.
+pub async fn init_once_async<F, Fut>(f: F)
+where
+    F: FnOnce() -> Fut + 'static,
+    Fut: Future<Output = ()> + 'static,
+{
+    let key = match (&f as &dyn Any).downcast_ref::<fn() -> Fut>() {
+        Some(p) => InitKey::Pointer(*p as usize),
+        None => type_key::<F>("init_once_per_key_async"),
+    };
+    cell(&ASYNC_CELLS, key).get_or_init(f).await;
+}
+
+/// Runs the async initializer only once per process for each key, regardless of which
+/// initializer is passed with the key.
+pub async fn init_once_per_key_async<F, Fut>(key: &str, f: F)
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = ()>,
+{
+    cell(&ASYNC_CELLS, InitKey::Name(key.to_string()))
+        .get_or_init(f)
+        .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    static A: AtomicUsize = AtomicUsize::new(0);
+    static B: AtomicUsize = AtomicUsize::new(0);
+    static C: AtomicUsize = AtomicUsize::new(0);
+    static D: AtomicUsize = AtomicUsize::new(0);
+    static E: AtomicUsize = AtomicUsize::new(0);
+
+    fn init_a() {
+        A.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn init_b() {
+        B.fetch_add(1, Ordering::SeqCst);
+    }
+
+    type AsyncInit = fn() -> Pin<Box<dyn Future<Output = ()>>>;
+
+    fn init_c() -> Pin<Box<dyn Future<Output = ()>>> {
+        Box::pin(async {
+            C.fetch_add(1, Ordering::SeqCst);
+        })
+    }
+
+    fn init_d() -> Pin<Box<dyn Future<Output = ()>>> {
+        Box::pin(async {
+            D.fetch_add(1, Ordering::SeqCst);
+        })
+    }
+
+    #[test]
+    fn function_pointers_run_once_each() {
+        for _ in 0..2 {
+            for f in [init_a, init_b] as [fn(); 2] {
+                init_once(f);
+            }
+        }
+        assert_eq!(A.load(Ordering::SeqCst), 1);
+        assert_eq!(B.load(Ordering::SeqCst), 1);
+    }
+
+    #[actix_web::test]
+    async fn async_function_pointers_run_once_each() {
+        for _ in 0..2 {
+            for f in [init_c, init_d] as [AsyncInit; 2] {
+                init_once_async(f).await;
+            }
+        }
+        assert_eq!(C.load(Ordering::SeqCst), 1);
+        assert_eq!(D.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn closures_run_once_per_expression() {
+        for n in 1..3 {
+            init_once(move || {
+                E.fetch_add(n, Ordering::SeqCst);
+            });
+        }
+        assert_eq!(E.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "init_once_per_key")]
+    fn boxed_initializers_are_refused() {
+        let f: Box<dyn FnOnce()> = Box::new(|| {});
+        init_once(f);
+    }
+}
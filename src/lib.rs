@@ -11,13 +11,47 @@
 //! (including http status code, data and errors).
 #![warn(missing_docs)]
 
-use serde::Deserialize;
-use serde_json::Value;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
-use actix_web::http::StatusCode;
+use actix_web::http::{Method, StatusCode};
 use actix_web::dev::ServiceResponse;
-use actix_web::body::{EitherBody, BoxBody};
-use actix_web::test;
+use actix_web::body::MessageBody;
+use futures_util::FutureExt;
+use std::panic::AssertUnwindSafe;
+
+mod check;
+mod diff;
+mod executor;
+mod expected;
+mod fixture;
+mod incremental;
+mod init;
+pub mod matchers;
+mod multipart;
+mod report;
+mod request;
+mod scenario;
+mod snapshot;
+mod sse;
+#[cfg(feature = "subscriptions")]
+mod subscription;
+
+pub use check::Expectation;
+pub use diff::{diff, diff_with, DiffOptions, Difference, DifferenceKind, ListOrder};
+pub use executor::Executor;
+pub use expected::{ExpectedError, HeaderMatcher, MessageMatcher};
+pub use fixture::{load_fixture, FixtureError};
+pub use incremental::{IncrementalExpected, IncrementalOutcome, MULTIPART_MIXED_MEDIA_TYPE};
+pub use init::{init_once, init_once_async, init_once_per_key, init_once_per_key_async};
+pub use multipart::Upload;
+pub use report::{Mismatch, TestFailure, TestOutcome};
+pub use request::{persisted_query_hash, GraphQLRequest};
+pub use scenario::{persisted_query_steps, test_scenario, try_test_scenario, Step};
+pub use snapshot::{Snapshot, REDACTED, UPDATE_SNAPSHOTS_VAR};
+pub use sse::{EventStreamExpected, EVENT_STREAM_MEDIA_TYPE};
+#[cfg(feature = "subscriptions")]
+pub use subscription::{ExpectedEvents, SubscriptionDriver, WsProtocol};
 
 /// A struct for deserializing a GraphQL response according to GraphQL specification
 #[derive(Deserialize, Debug)]
@@ -33,12 +67,33 @@ impl<T: PartialEq> GraphQLResponseReciever<T> {
     /// 'message' fields from all errors, with order maintained. If the optional errors field 
     /// is None, then an empty vector is returned. 
     pub fn get_messages(&self) -> Vec<String> {
+        self.collect_errors(|gre| gre.message.clone())
+    }
+
+    /// A convenience function for returning the error locations. Will return a vector of the 
+    /// 'locations' fields from all errors, with order maintained. If the optional errors field 
+    /// is None, then an empty vector is returned. 
+    pub fn get_locations(&self) -> Vec<Option<Vec<Location>>> {
+        self.collect_errors(|gre| gre.locations.clone())
+    }
+
+    /// A convenience function for returning the error paths. Will return a vector of the 'path'
+    /// fields from all errors, with order maintained. If the optional errors field is None, then 
+    /// an empty vector is returned. 
+    pub fn get_paths(&self) -> Vec<Option<Vec<PathSegment>>> {
+        self.collect_errors(|gre| gre.path.clone())
+    }
+
+    /// A convenience function for returning the error extensions. Will return a vector of the 
+    /// 'extensions' fields from all errors, with order maintained. If the optional errors field 
+    /// is None, then an empty vector is returned. 
+    pub fn get_extensions(&self) -> Vec<Option<Map<String, Value>>> {
+        self.collect_errors(|gre| gre.extensions.clone())
+    }
+
+    fn collect_errors<U, F: Fn(&GraphQLResponseError) -> U>(&self, f: F) -> Vec<U> {
         match &self.errors {
-            Some(s) => s
-                .iter()
-                .map(|gre: &GraphQLResponseError| &gre.message)
-                .cloned()
-                .collect(),
+            Some(s) => s.iter().map(f).collect(),
             None => {
                 vec![]
             }
@@ -46,130 +101,446 @@ impl<T: PartialEq> GraphQLResponseReciever<T> {
     }
 }
 
-/// A struct for deserializing an GraphQl error message according to GraphQL specification. Only 
-/// the 'message' field is implemented; 'locations' and 'paths' are ignored.
-#[derive(Deserialize, Debug)]
+/// A struct for deserializing an GraphQl error message according to GraphQL specification. The
+/// optional 'locations', 'path' and 'extensions' fields are None when absent from the response.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
 pub struct GraphQLResponseError {
     /// A string error message 
     pub message: String,
-    // locations field is not retrieved or compared in this context
-    // paths field is not retrieved or compared in this context
+    /// An optional vector of locations in the query document that caused the error.
+    pub locations: Option<Vec<Location>>,
+    /// An optional path to the response field which experienced the error. 
+    pub path: Option<Vec<PathSegment>>,
+    /// An optional map of additional, implementation specific, error information.
+    pub extensions: Option<Map<String, Value>>,
+}
+
+/// A location in a GraphQL query document, as reported in the 'locations' field of an error. 
+/// Both line and column are 1-indexed.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Location {
+    /// The line of the query document, starting from 1
+    pub line: u64,
+    /// The column of the query document, starting from 1
+    pub column: u64,
+}
+
+/// A single segment of the 'path' field of an error. Segments are field names for fields of an 
+/// object, or 0-indexed integers for elements of a list.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(untagged)]
+pub enum PathSegment {
+    /// A field name, or an alias if the field was aliased in the query
+    Field(String),
+    /// An index into a list field
+    Index(u64),
+}
+
+impl From<&str> for PathSegment {
+    fn from(field: &str) -> Self {
+        PathSegment::Field(field.to_string())
+    }
+}
+
+impl From<String> for PathSegment {
+    fn from(field: String) -> Self {
+        PathSegment::Field(field)
+    }
+}
+
+impl From<u64> for PathSegment {
+    fn from(index: u64) -> Self {
+        PathSegment::Index(index)
+    }
+}
+
+impl std::fmt::Display for PathSegment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PathSegment::Field(s) => write!(f, "{}", s),
+            PathSegment::Index(i) => write!(f, "{}", i),
+        }
+    }
 }
 
-/// A struct for passing the arguments to a GraphQL schema. The arguments consist of HTTP headers
-/// and a payload. 
+/// A struct for passing the arguments to a GraphQL schema. The arguments consist of HTTP headers,
+/// a payload and an optional http method. 
+/// 
+/// The payload may be written by hand, or built from a [GraphQLRequest] with [Argument::new].
+#[derive(Debug, Clone, Default)]
 pub struct Argument{
     /// A vector of header tuples, which consist of a pair of strings. 
     pub headers: Vec<(String, String)>,
     /// A string graphql payload. 
     pub payload: String,
+    /// An optional http method of the request. If None, the executing function chooses; an 
+    /// [Executor] uses its own method. With GET, an [Executor] sends the payload, which must be
+    /// a JSON [GraphQLRequest], as URL-encoded query parameters instead of a body. 
+    pub method: Option<Method>,
+    /// A vector of files to upload. If not empty, an [Executor] sends a `multipart/form-data` 
+    /// POST request following the GraphQL multipart request specification, with the payload as
+    /// the operations. 
+    pub files: Vec<Upload>,
+}
+
+impl Argument {
+    /// Creates an argument with a JSON 'Content-Type' header and the request serialized as the
+    /// payload. 
+    pub fn new(request: &GraphQLRequest) -> Self {
+        Argument {
+            headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+            payload: request.to_json(),
+            method: None,
+            files: vec![],
+        }
+    }
+
+    /// Creates an argument with a JSON 'Content-Type' header and a batch of requests serialized 
+    /// as a JSON array in the payload. 
+    pub fn batch(requests: &[GraphQLRequest]) -> Self {
+        Argument {
+            headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+            payload: serde_json::to_string(requests).expect("GraphQLRequest always serializes to JSON"),
+            method: None,
+            files: vec![],
+        }
+    }
+
+    /// Creates an argument for an automatic persisted query sent by its hash alone, with a JSON 
+    /// 'Content-Type' header. The query is omitted from the payload and its SHA-256 hash is added
+    /// to the extensions; a server that has not seen the query responds with a 
+    /// `PersistedQueryNotFound` error. To register the query, send 
+    /// `Argument::new(&request.with_persisted_query())` with the full query. 
+    pub fn persisted(request: &GraphQLRequest) -> Self {
+        Argument::new(&request.clone().with_persisted_query().without_query())
+    }
+
+    /// Creates an argument for a GET request, with the request as the payload to be URL-encoded.
+    pub fn get(request: &GraphQLRequest) -> Self {
+        Argument {
+            headers: vec![],
+            payload: request.to_json(),
+            method: Some(Method::GET),
+            files: vec![],
+        }
+    }
+
+    /// Adds a file to upload with the request.
+    pub fn with_file(mut self, file: Upload) -> Self {
+        self.files.push(file);
+        self
+    }
+
+    /// Sets the http method of the request.
+    pub fn with_method(mut self, method: Method) -> Self {
+        self.method = Some(method);
+        self
+    }
+
+    /// Adds a header to the argument.
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Deserializes the payload as a [GraphQLRequest]. Fails if the payload is not a JSON
+    /// GraphQL request body. 
+    pub fn request(&self) -> serde_json::Result<GraphQLRequest> {
+        serde_json::from_str(&self.payload)
+    }
+}
+
+impl From<GraphQLRequest> for Argument {
+    fn from(request: GraphQLRequest) -> Self {
+        Argument::new(&request)
+    }
 }
 
 /// A struct for defining the expected output of a GraphQL schema. Expected results consist of 
-/// an http status code, am optional vector of error messages, and some optional data. 
+/// an http status code, am optional vector of expected errors, and some optional data. 
+/// 
+/// [Expected::default] has status 200 OK and checks nothing else.
 pub struct Expected<V>{
     /// An http status code
     pub status: StatusCode,
-    /// An optional vector of String error messages. This should correspond to the 'message' fields 
-    /// of the array in the 'error' field, as defined in a GraphQL schema response map. 
-    pub errmsg: Option<Vec<String>>,
+    /// An optional vector of expected errors. This should correspond to the array in the 'errors'
+    /// field, as defined in a GraphQL schema response map. Errors are matched in order, and the 
+    /// number of errors must be equal. If the response has a plain text body rather than a 
+    /// GraphQL body, the text is matched as the message of a single error, and this field is 
+    /// required. 
+    pub errors: Option<Vec<ExpectedError>>,
     /// An optional data of the struct's type pa
     pub data: Option<V>,
+    /// Optional data as untyped JSON. Unless `strict` is set, this is a subset of the data: only 
+    /// the keys of objects given here are compared, and other keys in the response are ignored.
+    /// Values that vary between runs may be matched with [matchers]. May be used together with 
+    /// `data`. 
+    pub partial_data: Option<Value>,
+    /// If true, `partial_data` must match the response data exactly, with no extra keys. 
+    pub strict: bool,
+    /// A vector of header names and matchers for the values of the header in the response. 
+    /// Header names are case insensitive. 
+    pub headers: Vec<(String, HeaderMatcher)>,
+    /// An optional media type, such as `application/graphql-response+json`, compared with the
+    /// 'Content-Type' header of the response. Parameters such as `charset` are ignored, and the
+    /// comparison is case insensitive. 
+    pub content_type: Option<String>,
+    /// Which arrays in `data` and `partial_data` are compared in order, and which as multisets.
+    /// If any array is unordered, `data` is compared by its serialized JSON rather than with 
+    /// `PartialEq`. 
+    pub list_order: ListOrder,
+}
+
+impl<V> Expected<V> {
+    /// Creates an expectation of a 405 Method Not Allowed response, which the GraphQL over HTTP
+    /// specification requires in response to a mutation sent with GET. The body may be empty or
+    /// plain text of any message, or a GraphQL response with a single error of any message. 
+    pub fn method_not_allowed() -> Self {
+        Expected {
+            status: StatusCode::METHOD_NOT_ALLOWED,
+            errors: Some(vec![ExpectedError::any()]),
+            ..Default::default()
+        }
+    }
+
+    /// Creates an expectation of a response to an automatic persisted query unknown to the 
+    /// server, with a single error with the message `PersistedQueryNotFound`. 
+    pub fn persisted_query_not_found() -> Self {
+        Expected {
+            errors: Some(vec![ExpectedError::message("PersistedQueryNotFound")]),
+            ..Default::default()
+        }
+    }
+}
+
+impl<V> Default for Expected<V> {
+    fn default() -> Self {
+        Expected {
+            status: StatusCode::OK,
+            errors: None,
+            data: None,
+            partial_data: None,
+            strict: false,
+            headers: vec![],
+            content_type: None,
+            list_order: ListOrder::Ordered,
+        }
+    }
 }
 
+/// A struct for defining the expected output of a batch of GraphQL operations sent in one 
+/// request, to which the response is a JSON array of GraphQL responses. 
+/// 
+/// The status, headers and content type apply to the whole response. Each GraphQL response in the
+/// array is compared with the [Expected] in the same position, ignoring its status, headers and 
+/// content type. 
+pub struct BatchExpected<V> {
+    /// An http status code
+    pub status: StatusCode,
+    /// A vector of header names and matchers for the values of the header in the response. 
+    pub headers: Vec<(String, HeaderMatcher)>,
+    /// An optional media type, compared with the 'Content-Type' header of the response. 
+    pub content_type: Option<String>,
+    /// A vector of the expected responses, in the order of the operations of the batch. The number
+    /// of responses must be equal. 
+    pub responses: Vec<Expected<V>>,
+}
+
+impl<V> BatchExpected<V> {
+    /// Creates an expectation of a 200 OK response holding the expected responses. 
+    pub fn new(responses: Vec<Expected<V>>) -> Self {
+        BatchExpected {
+            status: StatusCode::OK,
+            headers: vec![],
+            content_type: None,
+            responses,
+        }
+    }
+}
+
+/// The media type of a GraphQL response body defined by the GraphQL over HTTP specification.
+pub const GRAPHQL_RESPONSE_MEDIA_TYPE: &str = "application/graphql-response+json";
+
 /// Executes tests against a defined environment using the actix_web framework.
 /// 
 /// Requires the following type parameters:
 /// - `FI` : An initializing function, which takes no arguments and returns no parameters. This can
-/// be used to execute code that is expected to run only one time across all parallel tests. 
+///   be used to execute code that is expected to run only one time across all parallel tests; it
+///   is run with [init_once], so each distinct function or closure runs once per process, and a
+///   boxed initializer panics. 
 /// - `FR` : A function to initialize the repository. This function must take as an argument 
-/// an optional JSON deserialziable data structure to be set as data in the repo. Returns `FutR`.
+///   the seed data of type `D` to be set as data in the repo. Returns `FutR`.
+/// - `D` : Seed data for the repository; there are no restrictions on this type. Owned seed data
+///   may be loaded from a JSON or YAML file with [load_fixture]. 
 /// - `FutR` : A future that resolves to a repository of type `R`.
 /// - `R` :  A repository; there are no restrictions on this type but it will be passed as argument
-/// to `FE`. 
+///   to `FE`. 
 /// - `FE` : An executing function that will run the test schema. Takes an [Argument] as argument
-/// and returns `FutE`.
+///   and returns `FutE`. An [Executor] provides this for an actix_web `App`. 
 /// - `FutE` : A future that resolves to an [actix_web::dev::ServiceResponse](https://docs.rs/actix-web/latest/actix_web/dev/struct.ServiceResponse.html)
-/// - `V` : The data type returned by the schema being tested by this framework. 
+///   with a body of type `B`.
+/// - `B` : The body of the response, such as the output of `test::call_service` for any actix_web
+///   app. Any [actix_web::body::MessageBody] is accepted. 
+/// - `E` : The [Expectation] of the response, such as [Expected] with data of the type returned by
+///   the schema being tested by this framework, or [BatchExpected] for a batch of operations. 
 /// 
 /// Takes the following function arguments:
 /// - `init_func` : An initializing function of type `FI`.
 /// - `repo_func` : A fuction to initialize the repository of type `FR`.
-/// - `repo_data` : Optional data used to initialize the repository. Must be a JSON deserializable 
-/// data structure. 
+/// - `repo_data` : Seed data of type `D`, used to initialize the repository. 
 /// - `arg` : [Argument] that is passed to the executing function
 /// - `exec_func` : An executing function of type `FE`.
-/// - `exp` : [Expected] return of the function, with any data of type `V`. 
+/// - `exp` : Expected return of the function, of type `E`. 
 /// 
 /// This function will execute the test with the defined initialization function, initialized 
-/// repository and arguments. Compares the resulting GraphQL response to the expected values using
-/// a series of asserts, which prints results from any test failures.
-pub async fn test_framework<'a, FI, FR, FutR, R, FE, FutE, V> (
+/// repository and arguments. Compares the resulting GraphQL response to the expected values, and 
+/// panics with a list of every mismatch if any are found. See [try_test_framework] for a variant
+/// that returns the mismatches instead. 
+pub async fn test_framework<FI, FR, D, FutR, R, FE, FutE, B, E> (
     init_func: FI,
     repo_func: FR,
-    repo_data: Option<&'a mut [Value]>,
+    repo_data: D,
     arg: Argument,
     exec_func: FE,
-    exp: Expected<V>,
+    exp: E,
 ) where 
-    FI: Fn(),
-    FR: Fn(Option<&'a mut [Value]>) -> FutR,
+    FI: Fn() + 'static,
+    FR: Fn(D) -> FutR,
+    FutR: std::future::Future<Output = R>,
+    FE: Fn(R, Argument) -> FutE,
+    FutE: std::future::Future<Output = ServiceResponse<B>>,
+    B: MessageBody,
+    E: Expectation,
+{
+    if let Err(failure) = try_test_framework(init_func, repo_func, repo_data, arg, exec_func, exp).await {
+        panic!("{}", failure);
+    }
+}
+
+/// Executes tests against a defined environment using the actix_web framework, without panicking
+/// on a mismatch. 
+/// 
+/// Takes the same type parameters and function arguments as [test_framework]. Returns the outcome
+/// of the expectation if the response matched every expected value, such as a [TestOutcome] 
+/// holding the decoded response for [Expected], or a [TestFailure] listing every mismatch 
+/// otherwise. 
+pub async fn try_test_framework<FI, FR, D, FutR, R, FE, FutE, B, E> (
+    init_func: FI,
+    repo_func: FR,
+    repo_data: D,
+    arg: Argument,
+    exec_func: FE,
+    exp: E,
+) -> Result<E::Outcome, TestFailure> where 
+    FI: Fn() + 'static,
+    FR: Fn(D) -> FutR,
     FutR: std::future::Future<Output = R>,
     FE: Fn(R, Argument) -> FutE,
-    FutE: std::future::Future<Output = ServiceResponse<EitherBody<BoxBody>>>,
-    V: serde::de::DeserializeOwned + PartialEq + std::fmt::Debug,
+    FutE: std::future::Future<Output = ServiceResponse<B>>,
+    B: MessageBody,
+    E: Expectation,
 {
-    init_func();
+    init_once(init_func);
 
     let repo: R = repo_func(repo_data).await;
     let response = exec_func(repo, arg).await;
 
-    // validate status is expected
-    let got_status = response.status();
-
-    assert_eq!(
-        got_status,
-        exp.status,
-        "Got unexpected status {}, expected {}; body: {:?}",
-        got_status,
-        exp.status,
-        test::read_body(response).await
-    );
-
-    // validate error or return, if required
-    if got_status == StatusCode::OK {
-        // success case
-        let got: GraphQLResponseReciever<V> = test::read_body_json(response).await;
-
-        match exp.errmsg {
-            Some(errmsg) => assert_eq!(got.get_messages(), errmsg),
-            None => {}
-        };
-
-        match exp.data {
-            Some(v) => {
-                let got_data = match got.data {
-                    Some(d) => d,
-                    None => {
-                        let msgs = got.get_messages();
-                        let msg = msgs.join("\n\t");
-                        panic!("Expected data from graphql response but did not get any. Error messages are: {}", msg)
-                    }
-                };
-                assert_eq!(got_data, v);
-            }
-            None => {}
-        };
-    } else {
-        // error case
-
-        let exp_err = &exp.errmsg
-            .expect("Expected an error message in case where status does is not 200 OK")[0];
+    exp.check(response).await
+}
 
-        let got_bytes = test::read_body(response).await;
-        let got_err = std::str::from_utf8(&got_bytes).unwrap();
+/// A struct holding async hooks that run around the execution of a test with 
+/// [test_framework_with_hooks]. 
+pub struct Hooks<FS, FT> {
+    /// A setup function, which takes a clone of the repository and returns a future. Runs after
+    /// the repository is initialized and before the executing function. 
+    pub setup: FS,
+    /// A teardown function, which takes the repository back after execution and returns a 
+    /// future. Runs after the response is compared to the expected values, even if the setup or
+    /// executing function panics. 
+    pub teardown: FT,
+}
 
-        assert_eq!(got_err, exp_err);
+/// Executes tests against a defined environment using the actix_web framework, with async setup 
+/// and teardown of the repository. 
+/// 
+/// Takes the same type parameters and function arguments as [test_framework], with the addition
+/// of `hooks`, a [Hooks] of a setup function of type `FS` resolving `FutS` and a teardown 
+/// function of type `FT` resolving `FutT`. The repository must be `Clone`, as the setup and 
+/// executing functions each take a clone and the teardown function takes the original; a 
+/// repository holding a connection pool or an `Arc` is cheap to clone. 
+/// 
+/// Panics with a list of every mismatch if any are found, after the teardown function has run.
+pub async fn test_framework_with_hooks<FI, FR, D, FutR, R, FS, FutS, FT, FutT, FE, FutE, B, E> (
+    init_func: FI,
+    repo_func: FR,
+    repo_data: D,
+    hooks: Hooks<FS, FT>,
+    arg: Argument,
+    exec_func: FE,
+    exp: E,
+) where 
+    FI: Fn() + 'static,
+    FR: Fn(D) -> FutR,
+    FutR: std::future::Future<Output = R>,
+    R: Clone,
+    FS: FnOnce(R) -> FutS,
+    FutS: std::future::Future<Output = ()>,
+    FT: FnOnce(R) -> FutT,
+    FutT: std::future::Future<Output = ()>,
+    FE: Fn(R, Argument) -> FutE,
+    FutE: std::future::Future<Output = ServiceResponse<B>>,
+    B: MessageBody,
+    E: Expectation,
+{
+    let result = try_test_framework_with_hooks(init_func, repo_func, repo_data, hooks, arg, exec_func, exp).await;
+    if let Err(failure) = result {
+        panic!("{}", failure);
     }
 }
 
+/// Executes tests against a defined environment using the actix_web framework, with async setup 
+/// and teardown of the repository, without panicking on a mismatch. 
+/// 
+/// Takes the same type parameters and function arguments as [test_framework_with_hooks], and 
+/// returns as [try_test_framework]. If the setup or executing function panics, the teardown 
+/// function runs before the panic is resumed. 
+pub async fn try_test_framework_with_hooks<FI, FR, D, FutR, R, FS, FutS, FT, FutT, FE, FutE, B, E> (
+    init_func: FI,
+    repo_func: FR,
+    repo_data: D,
+    hooks: Hooks<FS, FT>,
+    arg: Argument,
+    exec_func: FE,
+    exp: E,
+) -> Result<E::Outcome, TestFailure> where 
+    FI: Fn() + 'static,
+    FR: Fn(D) -> FutR,
+    FutR: std::future::Future<Output = R>,
+    R: Clone,
+    FS: FnOnce(R) -> FutS,
+    FutS: std::future::Future<Output = ()>,
+    FT: FnOnce(R) -> FutT,
+    FutT: std::future::Future<Output = ()>,
+    FE: Fn(R, Argument) -> FutE,
+    FutE: std::future::Future<Output = ServiceResponse<B>>,
+    B: MessageBody,
+    E: Expectation,
+{
+    init_once(init_func);
+
+    let repo: R = repo_func(repo_data).await;
+
+    let run = async {
+        (hooks.setup)(repo.clone()).await;
+        let response = exec_func(repo.clone(), arg).await;
+        exp.check(response).await
+    };
+    let result = AssertUnwindSafe(run).catch_unwind().await;
+
+    (hooks.teardown)(repo).await;
+
+    match result {
+        Ok(r) => r,
+        Err(panic) => std::panic::resume_unwind(panic),
+    }
+}
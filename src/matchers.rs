@@ -0,0 +1,160 @@
+//! Matchers for dynamic values in expected JSON data, such as generated ids and timestamps.
+//!
+//! Each function returns a JSON value that can be placed anywhere in [crate::Expected]'s
+//! `partial_data`, including inside the `serde_json::json!` macro. When the data is compared, a
+//! matcher is evaluated against the received value in its position instead of being compared for
+//! equality. A matcher is encoded as an object with a `"$matcher"` key, so expected data must not
+//! otherwise contain objects with that key.
+
+use regex::Regex;
+use serde_json::{json, Value};
+
+const MATCHER_KEY: &str = "$matcher";
+
+/// Matches any value, including null. The key must still be present in the response.
+pub fn any() -> Value {
+    json!({ MATCHER_KEY: "any" })
+}
+
+/// Matches a string holding a UUID in hyphenated form, of any version.
+pub fn uuid() -> Value {
+    json!({ MATCHER_KEY: "uuid" })
+}
+
+/// Matches a string holding an ISO 8601 date, or date and time with an optional fraction of a
+/// second and offset.
+pub fn iso8601() -> Value {
+    json!({ MATCHER_KEY: "iso8601" })
+}
+
+/// Matches a string containing a match for the regular expression. Use anchors to match the whole
+/// string. Panics if the pattern is not a valid regular expression.
+pub fn regex(pattern: &str) -> Value {
+    if let Err(e) = Regex::new(pattern) {
+        panic!("Invalid regular expression {:?} for matcher: {}", pattern, e);
+    }
+    json!({ MATCHER_KEY: "regex", "pattern": pattern })
+}
+
+/// Matches a number strictly greater than the value, which may be of any integer or floating
+/// point type. Numbers are compared as `f64`. Panics if the value is not a finite number.
+pub fn gt<N: Into<Value>>(value: N) -> Value {
+    let value = value.into();
+    if !value.is_number() {
+        panic!("Invalid value {} for matcher gt: expected a finite number", value);
+    }
+    json!({ MATCHER_KEY: "gt", "value": value })
+}
+
+/// Matches an array with exactly `n` elements, or a string with exactly `n` characters.
+pub fn len(n: usize) -> Value {
+    json!({ MATCHER_KEY: "len", "value": n })
+}
+
+/// A matcher decoded from expected JSON.
+#[derive(Debug, Clone)]
+pub(crate) enum Matcher {
+    Any,
+    Uuid,
+    Iso8601,
+    Regex(Regex),
+    Gt(f64),
+    Len(usize),
+}
+
+impl Matcher {
+    /// Decodes a matcher from an expected value. Returns None if the value is not a matcher.
+    pub(crate) fn from_value(value: &Value) -> Option<Matcher> {
+        let obj = value.as_object()?;
+        let kind = obj.get(MATCHER_KEY)?.as_str()?;
+        let arg = obj.get("value");
+
+        match kind {
+            "any" => Some(Matcher::Any),
+            "uuid" => Some(Matcher::Uuid),
+            "iso8601" => Some(Matcher::Iso8601),
+            "regex" => {
+                let pattern = obj.get("pattern")?.as_str()?;
+                Regex::new(pattern).ok().map(Matcher::Regex)
+            }
+            "gt" => arg?.as_f64().map(Matcher::Gt),
+            "len" => arg?.as_u64().map(|n| Matcher::Len(n as usize)),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the received value is matched.
+    pub(crate) fn matches(&self, got: &Value) -> bool {
+        match self {
+            Matcher::Any => true,
+            Matcher::Uuid => got.as_str().is_some_and(|s| uuid_regex().is_match(s)),
+            Matcher::Iso8601 => got.as_str().is_some_and(|s| iso8601_regex().is_match(s)),
+            Matcher::Regex(r) => got.as_str().is_some_and(|s| r.is_match(s)),
+            Matcher::Gt(n) => got.as_f64().is_some_and(|g| g > *n),
+            Matcher::Len(n) => match got {
+                Value::Array(a) => a.len() == *n,
+                Value::String(s) => s.chars().count() == *n,
+                _ => false,
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for Matcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Matcher::Any => write!(f, "any()"),
+            Matcher::Uuid => write!(f, "uuid()"),
+            Matcher::Iso8601 => write!(f, "iso8601()"),
+            Matcher::Regex(r) => write!(f, "regex({:?})", r.as_str()),
+            Matcher::Gt(n) => write!(f, "gt({})", n),
+            Matcher::Len(n) => write!(f, "len({})", n),
+        }
+    }
+}
+
+fn uuid_regex() -> &'static Regex {
+    static RE: std::sync::OnceLock<Regex> = std::sync::OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"^[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}$")
+            .unwrap()
+    })
+}
+
+fn iso8601_regex() -> &'static Regex {
+    static RE: std::sync::OnceLock<Regex> = std::sync::OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(
+            r"^\d{4}-\d{2}-\d{2}([T ]\d{2}:\d{2}(:\d{2}(\.\d+)?)?(Z|[+-]\d{2}(:?\d{2})?)?)?$",
+        )
+        .unwrap()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gt_matches(matcher: Value, got: Value) -> bool {
+        Matcher::from_value(&matcher).unwrap().matches(&got)
+    }
+
+    #[test]
+    fn gt_accepts_every_number_type() {
+        let count: usize = 2;
+        let id: i64 = -3;
+        let big: u64 = u64::MAX / 2;
+        assert!(gt_matches(gt(count), json!(3)));
+        assert!(!gt_matches(gt(count), json!(2)));
+        assert!(gt_matches(gt(id), json!(-2.5)));
+        assert!(gt_matches(gt(big), json!(u64::MAX)));
+        assert!(gt_matches(gt(0.5), json!(1)));
+        assert!(!gt_matches(gt(1u8), json!("2")));
+    }
+
+    #[test]
+    #[should_panic(expected = "expected a finite number")]
+    fn gt_refuses_nan() {
+        gt(f64::NAN);
+    }
+}
@@ -0,0 +1,237 @@
+//! Building `multipart/form-data` requests according to the GraphQL multipart request
+//! specification, for operations with `Upload` variables.
+
+use std::path::Path;
+
+use serde_json::Value;
+
+/// A file to upload with a GraphQL multipart request. The file is mapped to one or more paths in
+/// the operations, such as `variables.file` or `variables.files.0`, which are set to null in the
+/// 'operations' part of the request as the specification requires.
+#[derive(Debug, Clone)]
+pub struct Upload {
+    /// The paths in the operations that this file is mapped to.
+    pub paths: Vec<String>,
+    /// The file name sent with the file.
+    pub filename: String,
+    /// The media type of the file.
+    pub content_type: String,
+    /// The content of the file.
+    pub content: Vec<u8>,
+}
+
+impl Upload {
+    /// Creates an upload of in-memory content, mapped to a path in the operations.
+    pub fn new<C: Into<Vec<u8>>>(path: &str, filename: &str, content_type: &str, content: C) -> Self {
+        Upload {
+            paths: vec![path.to_string()],
+            filename: filename.to_string(),
+            content_type: content_type.to_string(),
+            content: content.into(),
+        }
+    }
+
+    /// Creates an upload of a file on disk, mapped to a path in the operations. The file name is
+    /// the last component of the file path. Fails if the file cannot be read.
+    pub fn from_file<P: AsRef<Path>>(path: &str, file: P, content_type: &str) -> std::io::Result<Self> {
+        let file = file.as_ref();
+        let filename = file
+            .file_name()
+            .map(|f| f.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Ok(Upload::new(path, &filename, content_type, std::fs::read(file)?))
+    }
+
+    /// Maps the file to another path in the operations, so that it is uploaded once and used for
+    /// several variables.
+    pub fn with_path(mut self, path: &str) -> Self {
+        self.paths.push(path.to_string());
+        self
+    }
+}
+
+/// Builds the body of a multipart request from JSON operations and files. Returns the boundary
+/// and the body. Fails if the operations are not valid JSON.
+pub(crate) fn multipart_body(operations: &str, files: &[Upload]) -> serde_json::Result<(String, Vec<u8>)> {
+    let mut ops: Value = serde_json::from_str(operations)?;
+    let mut map = serde_json::Map::new();
+    for (i, file) in files.iter().enumerate() {
+        for path in &file.paths {
+            set_null(&mut ops, path);
+        }
+        map.insert(i.to_string(), file.paths.clone().into());
+    }
+
+    let ops = ops.to_string();
+    let map = Value::Object(map).to_string();
+    let boundary = boundary(&ops, &map, files);
+
+    let mut body = vec![];
+    write_part(&mut body, &boundary, "operations", None, ops.as_bytes());
+    write_part(&mut body, &boundary, "map", None, map.as_bytes());
+    for (i, file) in files.iter().enumerate() {
+        write_part(&mut body, &boundary, &i.to_string(), Some(file), &file.content);
+    }
+    body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+
+    Ok((boundary, body))
+}
+
+fn write_part(body: &mut Vec<u8>, boundary: &str, name: &str, file: Option<&Upload>, content: &[u8]) {
+    body.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+    match file {
+        Some(f) => body.extend_from_slice(
+            format!(
+                "Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: {}\r\n\r\n",
+                name,
+                f.filename.replace('"', "\\\""),
+                f.content_type
+            )
+            .as_bytes(),
+        ),
+        None => body.extend_from_slice(
+            format!("Content-Disposition: form-data; name=\"{}\"\r\n\r\n", name).as_bytes(),
+        ),
+    }
+    body.extend_from_slice(content);
+    body.extend_from_slice(b"\r\n");
+}
+
+/// Chooses a boundary which does not occur in any part of the body.
+fn boundary(ops: &str, map: &str, files: &[Upload]) -> String {
+    let mut n = 0u64;
+    loop {
+        let b = format!("graphql-actix-test-boundary-{}", n);
+        let found = ops.contains(&b)
+            || map.contains(&b)
+            || files
+                .iter()
+                .any(|f| f.content.windows(b.len()).any(|w| w == b.as_bytes()));
+        if !found {
+            return b;
+        }
+        n += 1;
+    }
+}
+
+/// Sets the value at a dot separated path to null, creating objects and extending arrays as
+/// needed.
+fn set_null(value: &mut Value, path: &str) {
+    let mut cur = value;
+    for seg in path.split('.') {
+        cur = match cur {
+            Value::Array(a) => match seg.parse::<usize>() {
+                Ok(i) => {
+                    if a.len() <= i {
+                        a.resize(i + 1, Value::Null);
+                    }
+                    &mut a[i]
+                }
+                Err(_) => return,
+            },
+            v => {
+                if !v.is_object() {
+                    *v = Value::Object(Default::default());
+                }
+                v.as_object_mut()
+                    .expect("value was just made an object")
+                    .entry(seg)
+                    .or_insert(Value::Null)
+            }
+        };
+    }
+    *cur = Value::Null;
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_multipart::Multipart;
+    use actix_web::error::PayloadError;
+    use actix_web::http::header::{self, HeaderMap, HeaderValue};
+    use actix_web::web::Bytes;
+    use futures_util::{stream, StreamExt};
+    use serde_json::json;
+
+    use super::*;
+
+    /// A part of a multipart body: its name, and the file name and content.
+    type Part = (String, Option<String>, Vec<u8>);
+
+    /// Parses a multipart body back into its parts.
+    async fn parse(boundary: &str, body: Vec<u8>) -> Vec<Part> {
+        let mut headers = HeaderMap::new();
+        let content_type = format!("multipart/form-data; boundary={}", boundary);
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_str(&content_type).unwrap());
+        let body = stream::once(async { Ok::<_, PayloadError>(Bytes::from(body)) });
+
+        let mut multipart = Multipart::new(&headers, body);
+        let mut parts = vec![];
+        while let Some(field) = multipart.next().await {
+            let mut field = field.unwrap();
+            let disposition = field.content_disposition().unwrap();
+            let name = disposition.get_name().unwrap().to_string();
+            let filename = disposition.get_filename().map(str::to_string);
+            let mut content = vec![];
+            while let Some(chunk) = field.next().await {
+                content.extend_from_slice(&chunk.unwrap());
+            }
+            parts.push((name, filename, content));
+        }
+        parts
+    }
+
+    fn json_part(part: &Part) -> Value {
+        serde_json::from_slice(&part.2).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn body_follows_the_multipart_request_spec() {
+        let operations = json!({
+            "query": "mutation ($file: Upload!, $files: [Upload!]!) { upload(file: $file, files: $files) }",
+            "variables": {"file": "x", "files": [null, null]},
+        });
+        let files = [
+            Upload::new("variables.file", "a \"quoted\".txt", "text/plain", "alpha").with_path("variables.again"),
+            Upload::new("variables.files.0", "b.png", "image/png", vec![0, 1, 2]),
+            Upload::new("variables.files.1", "c.txt", "text/plain", "gamma"),
+        ];
+        let (boundary, body) = multipart_body(&operations.to_string(), &files).unwrap();
+        let parts = parse(&boundary, body).await;
+
+        let names: Vec<_> = parts.iter().map(|p| p.0.as_str()).collect();
+        assert_eq!(names, ["operations", "map", "0", "1", "2"]);
+        assert_eq!(
+            json_part(&parts[0])["variables"],
+            json!({"file": null, "again": null, "files": [null, null]})
+        );
+        assert_eq!(
+            json_part(&parts[1]),
+            json!({"0": ["variables.file", "variables.again"], "1": ["variables.files.0"], "2": ["variables.files.1"]})
+        );
+        assert_eq!(parts[2].1.as_deref(), Some("a \"quoted\".txt"));
+        assert_eq!(parts[2].2, b"alpha");
+        assert_eq!(parts[3].1.as_deref(), Some("b.png"));
+        assert_eq!(parts[3].2, [0, 1, 2]);
+        assert_eq!(parts[4].2, b"gamma");
+    }
+
+    #[actix_web::test]
+    async fn boundary_does_not_occur_in_the_files() {
+        let content = "--graphql-actix-test-boundary-0\r\n";
+        let files = [Upload::new("variables.file", "a.txt", "text/plain", content)];
+        let (boundary, body) = multipart_body(r#"{"query": "", "variables": {}}"#, &files).unwrap();
+        assert_eq!(boundary, "graphql-actix-test-boundary-1");
+
+        let parts = parse(&boundary, body).await;
+        assert_eq!(parts.len(), 3);
+        assert_eq!(parts[2].2, content.as_bytes());
+    }
+
+    #[test]
+    fn set_null_extends_arrays_and_creates_objects() {
+        let mut value = json!({"variables": {"files": []}});
+        set_null(&mut value, "variables.files.1");
+        set_null(&mut value, "variables.input.file");
+        assert_eq!(value, json!({"variables": {"files": [null, null], "input": {"file": null}}}));
+    }
+}
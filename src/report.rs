@@ -0,0 +1,269 @@
+//! Structures reporting the result of executing a test with [crate::try_test_framework].
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use actix_web::http::header::HeaderMap;
+use actix_web::http::StatusCode;
+use serde_json::Value;
+
+use crate::{Difference, GraphQLResponseError};
+
+/// The result of a test in which the response matched every expected value. Holds the parts of
+/// the GraphQL response, so that they can be inspected further.
+#[derive(Debug)]
+pub struct TestOutcome<V> {
+    /// The http status code of the response
+    pub status: StatusCode,
+    /// The headers of the response
+    pub headers: HeaderMap,
+    /// The media type of the 'Content-Type' header of the response, in lower case and without 
+    /// parameters. None if the header is absent.
+    pub content_type: Option<String>,
+    /// The data of the response, if the response had a GraphQL body with data.
+    pub data: Option<V>,
+    /// The errors of the response. Empty if the response had no errors.
+    pub errors: Vec<GraphQLResponseError>,
+    /// The GraphQL response as untyped JSON, if the response had a GraphQL body.
+    pub body: Option<Value>,
+}
+
+/// The result of a test in which the response did not match the expected values. Lists every
+/// mismatch found, rather than only the first.
+#[derive(Debug)]
+pub struct TestFailure {
+    /// A vector of every mismatch between the response and the expected values, in the order they
+    /// were checked. Never empty.
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl std::fmt::Display for TestFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "GraphQL response did not match expected, with {} mismatch(es):", self.mismatches.len())?;
+        for m in &self.mismatches {
+            write!(f, "\n  - {}", m)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for TestFailure {}
+
+/// A single difference between a response and the expected values.
+#[derive(Debug)]
+pub enum Mismatch {
+    /// The http status code was not the expected status code.
+    Status {
+        /// The expected status code
+        expected: StatusCode,
+        /// The status code of the response
+        got: StatusCode,
+        /// The body of the response, lossily decoded as UTF-8
+        body: String,
+    },
+    /// A header of the response did not match its expected matcher.
+    Header {
+        /// The name of the header
+        name: String,
+        /// A description of the matcher
+        expected: String,
+        /// Every value of the header in the response, lossily decoded as UTF-8
+        got: Vec<String>,
+    },
+    /// The media type of the response was not the expected media type.
+    ContentType {
+        /// The expected media type
+        expected: String,
+        /// The media type of the response, or None if it had no 'Content-Type' header
+        got: Option<String>,
+    },
+    /// The body of the response could not be read or decoded; contains the reason.
+    Body(String),
+    /// The response did not have the expected number of errors.
+    ErrorCount {
+        /// The expected number of errors
+        expected: usize,
+        /// The errors of the response
+        got: Vec<GraphQLResponseError>,
+    },
+    /// An error of the response did not match its expected error.
+    Error {
+        /// The position of the error in the 'errors' field of the response
+        index: usize,
+        /// A description of each field of the error that did not match
+        details: Vec<String>,
+    },
+    /// A plain text error body was received, but no error was expected.
+    ErrorBody {
+        /// A description of what was expected
+        expected: String,
+        /// The body of the response
+        got: String,
+    },
+    /// A batch response did not have the expected number of responses.
+    BatchCount {
+        /// The expected number of responses
+        expected: usize,
+        /// The number of responses in the batch
+        got: usize,
+    },
+    /// A response of a batch did not match its expected values.
+    BatchItem {
+        /// The position of the response in the batch
+        index: usize,
+        /// Every mismatch of the response
+        mismatches: Vec<Mismatch>,
+    },
+    /// A step of a scenario did not match its expected values. Later steps were not run.
+    Step {
+        /// The position of the step in the scenario
+        index: usize,
+        /// Every mismatch of the step
+        mismatches: Vec<Mismatch>,
+    },
+    /// A value to capture from a response of a scenario was not found.
+    Capture {
+        /// The name under which the value was to be captured
+        name: String,
+        /// The path of the value in the response, such as `data.users[0].id`
+        path: String,
+    },
+    /// An event of a subscription did not match its expected values.
+    Event {
+        /// The position of the event in the subscription
+        index: usize,
+        /// Every mismatch of the event
+        mismatches: Vec<Mismatch>,
+    },
+    /// A subscription did not have the expected number of events before it ended.
+    EventCount {
+        /// The expected number of events
+        expected: usize,
+        /// The number of events received
+        got: usize,
+    },
+    /// The server broke the protocol of a subscription, or closed the connection.
+    Protocol(String),
+    /// The server sent no message within the timeout.
+    Timeout {
+        /// How long the test waited
+        after: Duration,
+    },
+    /// The patches of an incremental response were not equal to the expected patches.
+    Patches {
+        /// Every difference between the expected patches and the patches of the response, with
+        /// paths rooted at `patches`
+        differences: Vec<Difference>,
+    },
+    /// The normalized response was not equal to the recorded snapshot.
+    Snapshot {
+        /// The path of the snapshot file
+        path: PathBuf,
+        /// Every difference between the snapshot and the normalized response
+        differences: Vec<Difference>,
+    },
+    /// The snapshot file was not valid JSON, and was replaced with the normalized response because
+    /// updates are enabled.
+    InvalidSnapshot {
+        /// The path of the snapshot file
+        path: PathBuf,
+        /// Why the file could not be parsed
+        error: String,
+    },
+    /// Data was expected, but the response had none.
+    MissingData {
+        /// The messages of any errors in the response
+        messages: Vec<String>,
+    },
+    /// The data of the response was not equal to the expected data.
+    Data {
+        /// Every difference between the serialized expected data and the serialized data of the
+        /// response, with paths rooted at `data`
+        differences: Vec<Difference>,
+    },
+}
+
+impl std::fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Mismatch::Status { expected, got, body } => {
+                write!(f, "Got unexpected status {}, expected {}; body: {:?}", got, expected, body)
+            }
+            Mismatch::Header { name, expected, got } => {
+                write!(f, "Header {:?} did not match: expected {}, got {:?}", name, expected, got)
+            }
+            Mismatch::ContentType { expected, got } => match got {
+                Some(got) => write!(f, "Got content type {:?}, expected {:?}", got, expected),
+                None => write!(f, "Got no content type, expected {:?}", expected),
+            },
+            Mismatch::Body(reason) => write!(f, "Could not decode response body: {}", reason),
+            Mismatch::ErrorCount { expected, got } => {
+                write!(f, "Got {} errors, expected {}; errors: {:?}", got.len(), expected, got)
+            }
+            Mismatch::Error { index, details } => {
+                write!(f, "errors[{}] did not match expected: {}", index, details.join("; "))
+            }
+            Mismatch::ErrorBody { expected, got } => {
+                write!(f, "Got error body {:?}, expected {}", got, expected)
+            }
+            Mismatch::BatchCount { expected, got } => {
+                write!(f, "Got {} responses in batch, expected {}", got, expected)
+            }
+            Mismatch::BatchItem { index, mismatches } => write_nested(f, "responses", *index, mismatches),
+            Mismatch::Step { index, mismatches } => write_nested(f, "steps", *index, mismatches),
+            Mismatch::Capture { name, path } => {
+                write!(f, "No value at {:?} to capture as {:?}", path, name)
+            }
+            Mismatch::Event { index, mismatches } => write_nested(f, "events", *index, mismatches),
+            Mismatch::EventCount { expected, got } => {
+                write!(f, "Got {} events before the subscription ended, expected {}", got, expected)
+            }
+            Mismatch::Protocol(s) => write!(f, "Subscription protocol failure: {}", s),
+            Mismatch::Timeout { after } => write!(f, "No message from the server within {:?}", after),
+            Mismatch::MissingData { messages } => write!(
+                f,
+                "Expected data from graphql response but did not get any. Error messages are: {}",
+                messages.join("\n\t")
+            ),
+            Mismatch::Patches { differences } => {
+                write!(f, "Patches did not match expected:")?;
+                for d in differences {
+                    write!(f, "\n      {}", d)?;
+                }
+                Ok(())
+            }
+            Mismatch::Snapshot { path, differences } => {
+                write!(f, "Response did not match snapshot {}:", path.display())?;
+                for d in differences {
+                    write!(f, "\n      {}", d)?;
+                }
+                write!(f, "\n      (set {}=1 to update the snapshot)", crate::UPDATE_SNAPSHOTS_VAR)
+            }
+            Mismatch::InvalidSnapshot { path, error } => {
+                write!(f, "Replaced invalid snapshot {}: {}", path.display(), error)
+            }
+            Mismatch::Data { differences } => {
+                write!(f, "Data did not match expected:")?;
+                for d in differences {
+                    write!(f, "\n      {}", d)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Writes the mismatches of one element of a batch, scenario or subscription as an indented list.
+fn write_nested(
+    f: &mut std::fmt::Formatter<'_>,
+    label: &str,
+    index: usize,
+    mismatches: &[Mismatch],
+) -> std::fmt::Result {
+    write!(f, "{}[{}] did not match expected:", label, index)?;
+    for m in mismatches {
+        let m = m.to_string().replace('\n', "\n      ");
+        write!(f, "\n      - {}", m)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,113 @@
+//! Structures for building the body of a GraphQL request.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+/// A struct for serializing a GraphQL request body according to the GraphQL over HTTP
+/// specification. Optional fields are omitted from the serialized body when they are None.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphQLRequest {
+    /// The GraphQL query document. May be empty for an automatic persisted query sent by its
+    /// hash alone, in which case it is omitted from the serialized body.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub query: String,
+    /// An optional name of the operation in the query document to execute.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub operation_name: Option<String>,
+    /// Optional values for the variables of the operation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variables: Option<Value>,
+    /// An optional map reserved for implementors to extend the protocol.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extensions: Option<Value>,
+}
+
+impl GraphQLRequest {
+    /// Creates a request for the query with no operation name, variables or extensions.
+    pub fn new(query: &str) -> Self {
+        GraphQLRequest {
+            query: query.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Sets the operation name.
+    pub fn with_operation_name(mut self, operation_name: &str) -> Self {
+        self.operation_name = Some(operation_name.to_string());
+        self
+    }
+
+    /// Sets the variables from any serializable type, which should serialize to a JSON map.
+    /// Panics if the variables fail to serialize.
+    pub fn with_variables<T: Serialize>(mut self, variables: T) -> Self {
+        self.variables = Some(to_value(variables, "variables"));
+        self
+    }
+
+    /// Sets the extensions from any serializable type, which should serialize to a JSON map.
+    /// Panics if the extensions fail to serialize.
+    pub fn with_extensions<T: Serialize>(mut self, extensions: T) -> Self {
+        self.extensions = Some(to_value(extensions, "extensions"));
+        self
+    }
+
+    /// Adds an automatic persisted query extension, as defined by Apollo, with the SHA-256 hash 
+    /// of the query: `{"persistedQuery": {"version": 1, "sha256Hash": ...}}`. Any other 
+    /// extensions are kept. 
+    pub fn with_persisted_query(mut self) -> Self {
+        let persisted = json!({"version": 1, "sha256Hash": persisted_query_hash(&self.query)});
+        match &mut self.extensions {
+            Some(Value::Object(map)) => {
+                map.insert("persistedQuery".to_string(), persisted);
+            }
+            _ => self.extensions = Some(json!({ "persistedQuery": persisted })),
+        }
+        self
+    }
+
+    /// Removes the query, so that an automatic persisted query is sent by its hash alone. 
+    pub fn without_query(mut self) -> Self {
+        self.query = String::new();
+        self
+    }
+
+    /// Serializes the request to a JSON string, suitable as the body of a POST request.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("GraphQLRequest always serializes to JSON")
+    }
+
+    /// Serializes the request to a URL-encoded query string, suitable for a GET request. The 
+    /// variables and extensions are encoded as JSON strings, as defined by the GraphQL over HTTP
+    /// specification. 
+    pub fn to_query_string(&self) -> String {
+        let mut params = vec![];
+        if !self.query.is_empty() {
+            params.push(("query", self.query.clone()));
+        }
+        if let Some(op) = &self.operation_name {
+            params.push(("operationName", op.clone()));
+        }
+        if let Some(v) = &self.variables {
+            params.push(("variables", v.to_string()));
+        }
+        if let Some(e) = &self.extensions {
+            params.push(("extensions", e.to_string()));
+        }
+        serde_urlencoded::to_string(params).expect("string pairs always URL-encode")
+    }
+}
+
+/// Returns the lower case hex SHA-256 hash of a query, as used to identify an automatic persisted
+/// query. 
+pub fn persisted_query_hash(query: &str) -> String {
+    format!("{:x}", Sha256::digest(query.as_bytes()))
+}
+
+fn to_value<T: Serialize>(value: T, field: &str) -> Value {
+    match serde_json::to_value(value) {
+        Ok(v) => v,
+        Err(e) => panic!("Failed to serialize GraphQL request {}: {}", field, e),
+    }
+}
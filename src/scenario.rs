@@ -0,0 +1,300 @@
+//! Running an ordered list of requests against the same repository, passing values captured from
+//! one response into later requests.
+
+use std::collections::HashMap;
+use std::future::Future;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::ServiceResponse;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{init_once, Argument, Expectation, Expected, GraphQLRequest, Mismatch, TestFailure, TestOutcome};
+
+/// A step of a scenario: an [Argument] to execute, the [Expected] response, and values to capture
+/// from the response for later steps.
+///
+/// Before a step is executed, every `{{name}}` in its payload and header values is replaced by the
+/// value captured under that name by an earlier step. In the payload, a placeholder inside a JSON
+/// string is replaced by the value escaped for the string, so that `"id": "{{id}}"` holds the
+/// captured string, and a placeholder outside a string by the value as JSON, so that
+/// `"id": {{id}}` holds a captured number or object. In a header value, a captured string is
+/// inserted as it is and any other value as JSON. A placeholder with no captured value is left
+/// unchanged.
+///
+/// Every step of a scenario has the same data type `V`. When the steps return data of different
+/// shapes, such as a scenario that creates, queries and then deletes a record, use
+/// `serde_json::Value` for `V` and compare the data of each step with `partial_data`, which may
+/// hold [crate::matchers] for generated values.
+pub struct Step<V> {
+    /// The argument passed to the executing function.
+    pub arg: Argument,
+    /// The expected response.
+    pub expected: Expected<V>,
+    /// A vector of names and paths of values to capture from the response, such as
+    /// `("id", "data.createUser.id")` or `("first", "data.users[0].id")`. Paths are written as in
+    /// a [crate::Difference].
+    pub captures: Vec<(String, String)>,
+}
+
+impl<V> Step<V> {
+    /// Creates a step that captures nothing.
+    pub fn new(arg: Argument, expected: Expected<V>) -> Self {
+        Step {
+            arg,
+            expected,
+            captures: vec![],
+        }
+    }
+
+    /// Captures the value at a path of the response, such as `data.users[0].id`, under a name. The
+    /// step fails if the response has no value at the path.
+    pub fn capture(mut self, name: &str, path: &str) -> Self {
+        self.captures.push((name.to_string(), path.to_string()));
+        self
+    }
+}
+
+/// Creates the steps of an automatic persisted query round trip. The first step sends the query
+/// by its hash alone and expects a `PersistedQueryNotFound` error; the second sends the full
+/// query with its hash and expects `expected`. A step sending the hash alone again may be pushed
+/// after these, to check that the server has stored the query.
+pub fn persisted_query_steps<V>(request: &GraphQLRequest, expected: Expected<V>) -> Vec<Step<V>> {
+    vec![
+        Step::new(Argument::persisted(request), Expected::persisted_query_not_found()),
+        Step::new(Argument::new(&request.clone().with_persisted_query()), expected),
+    ]
+}
+
+/// Executes a scenario of several steps against a defined environment using the actix_web
+/// framework.
+///
+/// Takes the same type parameters and function arguments as [crate::test_framework], except that
+/// `steps`, a vector of [Step] of one data type `V`, replaces the argument and expected values.
+/// The repository is initialized once, and the executing function is called with a clone of it
+/// for each step in order, so the repository must be `Clone`; a repository holding a connection
+/// pool or an `Arc` shares its state between steps.
+///
+/// Panics with the mismatches of the first step that does not match its expected values. Later
+/// steps are not run.
+pub async fn test_scenario<FI, FR, D, FutR, R, FE, FutE, B, V>(
+    init_func: FI,
+    repo_func: FR,
+    repo_data: D,
+    steps: Vec<Step<V>>,
+    exec_func: FE,
+) where
+    FI: Fn() + 'static,
+    FR: Fn(D) -> FutR,
+    FutR: Future<Output = R>,
+    R: Clone,
+    FE: Fn(R, Argument) -> FutE,
+    FutE: Future<Output = ServiceResponse<B>>,
+    B: MessageBody,
+    V: DeserializeOwned + Serialize + PartialEq + std::fmt::Debug,
+{
+    if let Err(failure) = try_test_scenario(init_func, repo_func, repo_data, steps, exec_func).await {
+        panic!("{}", failure);
+    }
+}
+
+/// Executes a scenario of several steps against a defined environment using the actix_web
+/// framework, without panicking on a mismatch.
+///
+/// Takes the same type parameters and function arguments as [test_scenario]. Returns a
+/// [TestOutcome] for every step if each matched its expected values, or a [TestFailure] holding a
+/// single [Mismatch::Step] for the first step that did not.
+pub async fn try_test_scenario<FI, FR, D, FutR, R, FE, FutE, B, V>(
+    init_func: FI,
+    repo_func: FR,
+    repo_data: D,
+    steps: Vec<Step<V>>,
+    exec_func: FE,
+) -> Result<Vec<TestOutcome<V>>, TestFailure>
+where
+    FI: Fn() + 'static,
+    FR: Fn(D) -> FutR,
+    FutR: Future<Output = R>,
+    R: Clone,
+    FE: Fn(R, Argument) -> FutE,
+    FutE: Future<Output = ServiceResponse<B>>,
+    B: MessageBody,
+    V: DeserializeOwned + Serialize + PartialEq + std::fmt::Debug,
+{
+    init_once(init_func);
+
+    let repo: R = repo_func(repo_data).await;
+
+    let mut captured: HashMap<String, Value> = HashMap::new();
+    let mut outcomes = vec![];
+    for (index, step) in steps.into_iter().enumerate() {
+        let arg = interpolate_argument(step.arg, &captured);
+        let response = exec_func(repo.clone(), arg).await;
+
+        let outcome = match step.expected.check(response).await {
+            Ok(o) => o,
+            Err(failure) => return Err(step_failure(index, failure.mismatches)),
+        };
+
+        let mut mismatches = vec![];
+        for (name, path) in step.captures {
+            match outcome.body.as_ref().and_then(|b| value_at(b, &path)) {
+                Some(v) => {
+                    captured.insert(name, v.clone());
+                }
+                None => mismatches.push(Mismatch::Capture { name, path }),
+            }
+        }
+        if !mismatches.is_empty() {
+            return Err(step_failure(index, mismatches));
+        }
+
+        outcomes.push(outcome);
+    }
+
+    Ok(outcomes)
+}
+
+fn step_failure(index: usize, mismatches: Vec<Mismatch>) -> TestFailure {
+    TestFailure {
+        mismatches: vec![Mismatch::Step { index, mismatches }],
+    }
+}
+
+fn interpolate_argument(mut arg: Argument, captured: &HashMap<String, Value>) -> Argument {
+    if captured.is_empty() {
+        return arg;
+    }
+    arg.payload = interpolate_json(&arg.payload, captured);
+    for (_, value) in arg.headers.iter_mut() {
+        *value = interpolate_text(value, captured);
+    }
+    arg
+}
+
+/// Replaces every `{{name}}` in JSON text with the captured value of that name, escaped if the
+/// placeholder is inside a string and as JSON otherwise.
+fn interpolate_json(text: &str, captured: &HashMap<String, Value>) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut rest = text;
+
+    while let Some(c) = rest.chars().next() {
+        if !escaped {
+            if let Some((value, len)) = placeholder(rest, captured) {
+                if in_string {
+                    let quoted = serde_json::to_string(&as_text(value)).expect("strings always serialize");
+                    out.push_str(&quoted[1..quoted.len() - 1]);
+                } else {
+                    out.push_str(&value.to_string());
+                }
+                rest = &rest[len..];
+                continue;
+            }
+        }
+
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+        } else if c == '"' {
+            in_string = true;
+        }
+        out.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    out
+}
+
+/// Replaces every `{{name}}` in plain text with the captured value of that name.
+fn interpolate_text(text: &str, captured: &HashMap<String, Value>) -> String {
+    let mut out = text.to_string();
+    for (name, value) in captured {
+        out = out.replace(&format!("{{{{{}}}}}", name), &as_text(value));
+    }
+    out
+}
+
+/// Returns the captured value of a placeholder at the start of the text, and the length of the
+/// placeholder.
+fn placeholder<'a>(text: &str, captured: &'a HashMap<String, Value>) -> Option<(&'a Value, usize)> {
+    let inner = text.strip_prefix("{{")?;
+    let end = inner.find("}}")?;
+    captured.get(&inner[..end]).map(|v| (v, end + 4))
+}
+
+/// Returns a string as it is, and any other value as JSON.
+fn as_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+/// Returns the value at a path such as `data.users[0].id`, written as in a [crate::Difference].
+fn value_at<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    let mut cur = value;
+    for part in path.split('.') {
+        let (field, mut indexes) = match part.find('[') {
+            Some(i) => part.split_at(i),
+            None => (part, ""),
+        };
+        if !field.is_empty() {
+            cur = cur.as_object()?.get(field)?;
+        }
+        while let Some(index) = indexes.strip_prefix('[') {
+            let end = index.find(']')?;
+            cur = cur.as_array()?.get(index[..end].parse::<usize>().ok()?)?;
+            indexes = &index[end + 1..];
+        }
+        if !indexes.is_empty() {
+            return None;
+        }
+    }
+    Some(cur)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn captured() -> HashMap<String, Value> {
+        HashMap::from([
+            ("name".to_string(), json!("O\"Brien \\ x")),
+            ("id".to_string(), json!(7)),
+        ])
+    }
+
+    #[test]
+    fn interpolation_escapes_strings_in_json() {
+        let payload = interpolate_json(r#"{"n": "{{name}}", "id": {{id}}, "s": "a\"{{id}}"}"#, &captured());
+        let payload: Value = serde_json::from_str(&payload).unwrap();
+        assert_eq!(payload, json!({"n": "O\"Brien \\ x", "id": 7, "s": "a\"7"}));
+    }
+
+    #[test]
+    fn interpolation_inserts_strings_as_json_outside_strings() {
+        assert_eq!(interpolate_json("[{{name}}, {{other}}]", &captured()), r#"["O\"Brien \\ x", {{other}}]"#);
+    }
+
+    #[test]
+    fn interpolation_inserts_raw_text_in_headers() {
+        assert_eq!(interpolate_text("Bearer {{name}}-{{id}}", &captured()), "Bearer O\"Brien \\ x-7");
+    }
+
+    #[test]
+    fn value_at_reads_difference_paths() {
+        let body = json!({"data": {"users": [{"id": 1}, {"id": 2, "tags": [["a"]]}]}});
+        assert_eq!(value_at(&body, "data.users[1].id"), Some(&json!(2)));
+        assert_eq!(value_at(&body, "data.users[1].tags[0][0]"), Some(&json!("a")));
+        assert_eq!(value_at(&body, "data.users[2].id"), None);
+        assert_eq!(value_at(&body, "data.users.0.id"), None);
+    }
+}
@@ -0,0 +1,338 @@
+//! Snapshot testing of responses, comparing the normalized response with one recorded in a file.
+
+use std::path::{Path, PathBuf};
+
+use actix_web::body::MessageBody;
+use actix_web::dev::ServiceResponse;
+use actix_web::http::StatusCode;
+use serde_json::{json, Map, Value};
+
+use crate::check::read_envelope;
+use crate::diff::{field_path, index_path, wildcard_path};
+use crate::incremental::{merge_incremental, MULTIPART_MIXED_MEDIA_TYPE};
+use crate::{diff, Expectation, Mismatch, TestFailure};
+
+/// The environment variable which, when set to any value other than `0`, makes a [Snapshot]
+/// overwrite its file with the response rather than failing on a difference.
+pub const UPDATE_SNAPSHOTS_VAR: &str = "UPDATE_SNAPSHOTS";
+
+/// The value that [Snapshot::redact] puts in place of a volatile value.
+pub const REDACTED: &str = "[redacted]";
+
+/// Creates a [Snapshot] with the name, stored in a `.snap` file in the same directory as the test
+/// source file that calls the macro, such as `tests/users.snap` for `snapshot!("users")` in
+/// `tests/api.rs`.
+#[macro_export]
+macro_rules! snapshot {
+    ($name:expr) => {
+        $crate::Snapshot::next_to(file!(), env!("CARGO_MANIFEST_DIR"), $name)
+    };
+}
+
+/// An expectation that the response matches a snapshot recorded in a JSON file, for use with
+/// [crate::test_framework].
+///
+/// The response is normalized to a JSON object of its status and the `data` and `errors` of its
+/// GraphQL body, or its `body` as text if it is not GraphQL; an incremental response is merged.
+/// Redactions are then applied. If the file does not exist, the normalized response is written to
+/// it and the test passes. Otherwise the response is compared with the file, which may be edited
+/// by hand to use [crate::matchers]. If [UPDATE_SNAPSHOTS_VAR] is set, a response that does not
+/// match is written to the file instead of failing. A file that is not valid JSON makes the test
+/// panic, or if updates are enabled, is replaced and fails it with [Mismatch::InvalidSnapshot].
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    /// The path of the snapshot file.
+    pub path: PathBuf,
+    /// A vector of paths and the values that replace the values at those paths, applied to the
+    /// response before it is written or compared. Paths are written as in a
+    /// [crate::Difference] rooted at the normalized response, such as `data.user.createdAt`, and
+    /// may have `[*]` in place of every array index, such as `data.users[*].id`.
+    pub redactions: Vec<(String, Value)>,
+}
+
+impl Snapshot {
+    /// Creates a snapshot stored in the file at the path, with no redactions.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Snapshot {
+            path: path.as_ref().to_path_buf(),
+            redactions: vec![],
+        }
+    }
+
+    /// Creates a snapshot stored in `<name>.snap` in the directory of a source file, whose path is
+    /// given as by `file!()` relative to the package or to a workspace containing it. Used by
+    /// [snapshot!].
+    pub fn next_to(source_file: &str, manifest_dir: &str, name: &str) -> Self {
+        let manifest_dir = Path::new(manifest_dir);
+        let source = manifest_dir
+            .ancestors()
+            .map(|dir| dir.join(source_file))
+            .find(|p| p.exists())
+            .unwrap_or_else(|| manifest_dir.join(source_file));
+        let dir = source.parent().unwrap_or(manifest_dir);
+        Snapshot::new(dir.join(format!("{}.snap", name)))
+    }
+
+    /// Replaces the value at a path with [REDACTED].
+    pub fn redact(self, path: &str) -> Self {
+        self.redact_with(path, REDACTED)
+    }
+
+    /// Replaces the value at a path with another value.
+    pub fn redact_with<T: Into<Value>>(mut self, path: &str, replacement: T) -> Self {
+        self.redactions.push((path.to_string(), replacement.into()));
+        self
+    }
+}
+
+impl Expectation for Snapshot {
+    type Outcome = Value;
+
+    async fn check<B: MessageBody>(self, response: ServiceResponse<B>) -> Result<Value, TestFailure> {
+        let update = std::env::var(UPDATE_SNAPSHOTS_VAR).is_ok_and(|v| v != "0");
+        check_snapshot(response, self, update).await
+    }
+}
+
+/// Normalizes a response and compares it with the snapshot, writing the snapshot if it does not
+/// exist or `update` is true. Panics if the snapshot file cannot be read or written, or is invalid
+/// and `update` is false.
+async fn check_snapshot<B: MessageBody>(
+    response: ServiceResponse<B>,
+    exp: Snapshot,
+    update: bool,
+) -> Result<Value, TestFailure> {
+    let mut mismatches = vec![];
+
+    // the status is compared with the snapshot, so any status is accepted here
+    let status = response.status();
+    let envelope = match read_envelope(response, status, &[], None, &mut mismatches).await {
+        Some(e) => e,
+        None => return Err(TestFailure { mismatches }),
+    };
+
+    let mut body = envelope.body.to_vec();
+    if envelope.media_type.as_deref() == Some(MULTIPART_MIXED_MEDIA_TYPE) {
+        match merge_incremental(&envelope.headers, &body) {
+            Ok(merged) => body = merged.result.to_string().into_bytes(),
+            Err(e) => {
+                mismatches.push(Mismatch::Body(e));
+                return Err(TestFailure { mismatches });
+            }
+        }
+    }
+
+    let mut got = normalize(envelope.status, &body);
+    for (pattern, replacement) in &exp.redactions {
+        redact(&mut got, "", pattern, replacement);
+    }
+
+    let recorded = match std::fs::read_to_string(&exp.path) {
+        Ok(s) => match serde_json::from_str::<Value>(&s) {
+            Ok(v) => Some(v),
+            Err(e) if update => {
+                write_snapshot(&exp.path, &got);
+                return Err(TestFailure {
+                    mismatches: vec![Mismatch::InvalidSnapshot {
+                        path: exp.path,
+                        error: e.to_string(),
+                    }],
+                });
+            }
+            Err(e) => panic!("Invalid snapshot {}: {}", exp.path.display(), e),
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => panic!("Failed to read snapshot {}: {}", exp.path.display(), e),
+    };
+
+    match recorded {
+        Some(recorded) => {
+            let differences = diff(&recorded, &got, "");
+            if differences.is_empty() {
+                return Ok(got);
+            }
+            if !update {
+                return Err(TestFailure {
+                    mismatches: vec![Mismatch::Snapshot {
+                        path: exp.path,
+                        differences,
+                    }],
+                });
+            }
+            write_snapshot(&exp.path, &got);
+        }
+        None => write_snapshot(&exp.path, &got),
+    }
+    Ok(got)
+}
+
+/// Normalizes a response to its status and the data and errors of its GraphQL body, or its body
+/// as text.
+fn normalize(status: StatusCode, body: &[u8]) -> Value {
+    let mut out = Map::new();
+    out.insert("status".to_string(), json!(status.as_u16()));
+
+    match serde_json::from_slice::<Value>(body) {
+        Ok(Value::Object(mut graphql)) if graphql.contains_key("data") || graphql.contains_key("errors") => {
+            for key in ["data", "errors"] {
+                if let Some(v) = graphql.remove(key) {
+                    out.insert(key.to_string(), v);
+                }
+            }
+        }
+        _ => {
+            out.insert("body".to_string(), json!(String::from_utf8_lossy(body)));
+        }
+    }
+    Value::Object(out)
+}
+
+/// Replaces every value whose path matches the pattern.
+fn redact(value: &mut Value, path: &str, pattern: &str, replacement: &Value) {
+    if !path.is_empty() && (path == pattern || wildcard_path(path) == pattern) {
+        *value = replacement.clone();
+        return;
+    }
+    match value {
+        Value::Object(map) => {
+            for (key, v) in map.iter_mut() {
+                redact(v, &field_path(path, key), pattern, replacement);
+            }
+        }
+        Value::Array(items) => {
+            for (i, v) in items.iter_mut().enumerate() {
+                redact(v, &index_path(path, i), pattern, replacement);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn write_snapshot(path: &Path, value: &Value) {
+    if let Some(dir) = path.parent() {
+        if let Err(e) = std::fs::create_dir_all(dir) {
+            panic!("Failed to create snapshot directory {}: {}", dir.display(), e);
+        }
+    }
+    let mut contents = serde_json::to_string_pretty(value).expect("JSON values always serialize");
+    contents.push('\n');
+    if let Err(e) = std::fs::write(path, contents) {
+        panic!("Failed to write snapshot {}: {}", path.display(), e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+    use actix_web::HttpResponse;
+
+    use super::*;
+    use crate::DifferenceKind;
+
+    /// Creates an empty directory for the snapshots of a test.
+    fn snapshot_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("graphql-actix-test-{}-{}", std::process::id(), test));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    async fn check(body: Value, snapshot: &Snapshot, update: bool) -> Result<Value, TestFailure> {
+        let response = TestRequest::default().to_srv_response(HttpResponse::Ok().json(body));
+        check_snapshot(response, snapshot.clone(), update).await
+    }
+
+    fn read(snapshot: &Snapshot) -> Value {
+        serde_json::from_str(&std::fs::read_to_string(&snapshot.path).unwrap()).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn writes_a_missing_snapshot_and_then_matches_it() {
+        let dir = snapshot_dir("missing");
+        let snapshot = Snapshot::new(dir.join("nested").join("user.snap"));
+        let body = json!({"data": {"user": {"name": "a"}}, "extensions": {"cost": 1}});
+
+        let got = check(body.clone(), &snapshot, false).await.unwrap();
+        assert_eq!(got, json!({"status": 200, "data": {"user": {"name": "a"}}}));
+        assert_eq!(read(&snapshot), got);
+
+        assert_eq!(check(body, &snapshot, false).await.unwrap(), got);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn reports_differences_from_the_snapshot() {
+        let dir = snapshot_dir("differences");
+        let snapshot = Snapshot::new(dir.join("user.snap"));
+        check(json!({"data": {"user": {"name": "a"}}}), &snapshot, false).await.unwrap();
+
+        let failure = check(json!({"data": {"user": {"name": "b"}}}), &snapshot, false).await.unwrap_err();
+        match &failure.mismatches[..] {
+            [Mismatch::Snapshot { path, differences }] => {
+                assert_eq!(path, &snapshot.path);
+                assert_eq!(differences.len(), 1);
+                assert_eq!(differences[0].path, "data.user.name");
+                assert!(matches!(differences[0].kind, DifferenceKind::Changed { .. }));
+            }
+            other => panic!("expected a snapshot mismatch, got {:?}", other),
+        }
+        assert_eq!(read(&snapshot)["data"]["user"]["name"], "a");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn redacts_every_element_of_a_wildcard_path() {
+        let dir = snapshot_dir("redact");
+        let snapshot = Snapshot::new(dir.join("users.snap"))
+            .redact("data.users[*].id")
+            .redact_with("data.at", 0);
+        let body = json!({"data": {"users": [{"id": 1, "name": "a"}, {"id": 2, "name": "b"}], "at": "12:00"}});
+
+        let got = check(body, &snapshot, false).await.unwrap();
+        assert_eq!(
+            got["data"],
+            json!({"users": [{"id": REDACTED, "name": "a"}, {"id": REDACTED, "name": "b"}], "at": 0})
+        );
+
+        // the redacted values may change between runs
+        let body = json!({"data": {"users": [{"id": 3, "name": "a"}, {"id": 4, "name": "b"}], "at": "13:00"}});
+        check(body, &snapshot, false).await.unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn update_overwrites_a_different_snapshot() {
+        let dir = snapshot_dir("update");
+        let snapshot = Snapshot::new(dir.join("user.snap"));
+        check(json!({"data": {"user": "a"}}), &snapshot, false).await.unwrap();
+
+        check(json!({"data": {"user": "b"}}), &snapshot, true).await.unwrap();
+        assert_eq!(read(&snapshot)["data"]["user"], "b");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[actix_web::test]
+    async fn update_replaces_an_invalid_snapshot_and_reports_it() {
+        let dir = snapshot_dir("invalid");
+        let snapshot = Snapshot::new(dir.join("user.snap"));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(&snapshot.path, "not json").unwrap();
+
+        let failure = check(json!({"data": {"user": "a"}}), &snapshot, true).await.unwrap_err();
+        assert!(matches!(&failure.mismatches[..], [Mismatch::InvalidSnapshot { .. }]));
+        assert_eq!(read(&snapshot)["data"]["user"], "a");
+
+        check(json!({"data": {"user": "a"}}), &snapshot, false).await.unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[actix_web::test]
+    #[should_panic(expected = "Invalid snapshot")]
+    async fn an_invalid_snapshot_panics_without_update() {
+        let dir = snapshot_dir("invalid-panic");
+        let snapshot = Snapshot::new(dir.join("user.snap"));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(&snapshot.path, "not json").unwrap();
+
+        let _ = check(json!({"data": {"user": "a"}}), &snapshot, false).await;
+    }
+}
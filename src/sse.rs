@@ -0,0 +1,295 @@
+//! Reading GraphQL subscriptions served as Server-Sent Events, following the "distinct
+//! connections" mode of the GraphQL over Server-Sent Events protocol.
+
+use std::time::Duration;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::ServiceResponse;
+use actix_web::http::StatusCode;
+use actix_web::test;
+use futures_util::future::poll_fn;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::check::{check_graphql_value, header_mismatches, media_type};
+use crate::{Expectation, Expected, HeaderMatcher, Mismatch, TestFailure, TestOutcome};
+
+/// The media type of a Server-Sent Events response body.
+pub const EVENT_STREAM_MEDIA_TYPE: &str = "text/event-stream";
+
+/// A struct for defining the expected events of a subscription served as Server-Sent Events, for
+/// use with [crate::test_framework]. The request should have an 'Accept' header of
+/// [EVENT_STREAM_MEDIA_TYPE].
+///
+/// The response must have a 'Content-Type' of [EVENT_STREAM_MEDIA_TYPE]. The body is read as it
+/// is streamed, and the data of each `next` event is compared with the [Expected] in the same
+/// position, ignoring its status, headers and content type. A `complete` event ends the
+/// subscription.
+pub struct EventStreamExpected<V> {
+    /// An http status code
+    pub status: StatusCode,
+    /// A vector of header names and matchers for the values of the header in the response.
+    pub headers: Vec<(String, HeaderMatcher)>,
+    /// A vector of the expected events, in the order the server sends them.
+    pub events: Vec<Expected<V>>,
+    /// If true, the server must send a `complete` event after the events, and no further events
+    /// are allowed. If false, the body is dropped once every expected event has been received.
+    pub complete: bool,
+    /// How long to wait for each part of the body. Defaults to 5 seconds.
+    pub timeout: Duration,
+}
+
+impl<V> EventStreamExpected<V> {
+    /// Creates an expectation of a 200 OK stream of the events, followed by a `complete` event.
+    pub fn new(events: Vec<Expected<V>>) -> Self {
+        EventStreamExpected {
+            status: StatusCode::OK,
+            headers: vec![],
+            events,
+            complete: true,
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    /// Creates an expectation of a 200 OK stream starting with the events, after which the body
+    /// is dropped.
+    pub fn first(events: Vec<Expected<V>>) -> Self {
+        EventStreamExpected {
+            complete: false,
+            ..EventStreamExpected::new(events)
+        }
+    }
+
+    /// Sets how long to wait for each part of the body.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl<V> Expectation for EventStreamExpected<V>
+where
+    V: DeserializeOwned + Serialize + PartialEq + std::fmt::Debug,
+{
+    type Outcome = Vec<TestOutcome<V>>;
+
+    async fn check<B: MessageBody>(self, response: ServiceResponse<B>) -> Result<Vec<TestOutcome<V>>, TestFailure> {
+        check_event_stream(response, self).await
+    }
+}
+
+/// Compares a streamed response with the expected events, collecting every mismatch.
+async fn check_event_stream<B, V>(
+    response: ServiceResponse<B>,
+    exp: EventStreamExpected<V>,
+) -> Result<Vec<TestOutcome<V>>, TestFailure>
+where
+    B: MessageBody,
+    V: DeserializeOwned + Serialize + PartialEq + std::fmt::Debug,
+{
+    let status = response.status();
+    let headers = response.headers().clone();
+    let got_media_type = media_type(&response);
+    let mut mismatches = header_mismatches(&headers, &exp.headers);
+
+    // a response that is not an event stream, such as an error, is read whole for the report
+    if status != exp.status || got_media_type.as_deref() != Some(EVENT_STREAM_MEDIA_TYPE) {
+        let body = match tokio::time::timeout(exp.timeout, test::try_read_body(response)).await {
+            Ok(Ok(b)) => String::from_utf8_lossy(&b).into_owned(),
+            _ => String::new(),
+        };
+        if status != exp.status {
+            mismatches.insert(0, Mismatch::Status {
+                expected: exp.status,
+                got: status,
+                body,
+            });
+        } else {
+            mismatches.push(Mismatch::ContentType {
+                expected: EVENT_STREAM_MEDIA_TYPE.to_string(),
+                got: got_media_type,
+            });
+        }
+        return Err(TestFailure { mismatches });
+    }
+
+    let body = response.into_body();
+    let mut body = std::pin::pin!(body);
+    let mut parser = EventParser::default();
+
+    let mut outcomes = vec![];
+    let expected_count = exp.events.len();
+    let mut expected = exp.events.into_iter();
+    let mut got = 0;
+
+    while exp.complete || got < expected_count {
+        let event = match parser.next_event() {
+            Some(e) => e,
+            None => match tokio::time::timeout(exp.timeout, poll_fn(|cx| body.as_mut().poll_next(cx))).await {
+                Ok(Some(Ok(chunk))) => {
+                    parser.push(&chunk);
+                    continue;
+                }
+                Ok(Some(Err(e))) => {
+                    let e: Box<dyn std::error::Error> = e.into();
+                    mismatches.push(Mismatch::Body(format!("failed to read body: {}", e)));
+                    break;
+                }
+                Ok(None) => {
+                    mismatches.push(Mismatch::Protocol("event stream ended without a complete event".to_string()));
+                    break;
+                }
+                Err(_) => {
+                    mismatches.push(Mismatch::Timeout { after: exp.timeout });
+                    break;
+                }
+            },
+        };
+
+        match event.event.as_str() {
+            "next" => {}
+            "complete" => break,
+            other => {
+                mismatches.push(Mismatch::Protocol(format!("unexpected event {:?}: {}", other, event.data)));
+                break;
+            }
+        }
+
+        if let Some(event_exp) = expected.next() {
+            let mut outcome = TestOutcome {
+                status,
+                headers: headers.clone(),
+                content_type: got_media_type.clone(),
+                data: None,
+                errors: vec![],
+                body: None,
+            };
+            let event_mismatches = match serde_json::from_str::<Value>(&event.data) {
+                Ok(v) => check_graphql_value(v, event_exp, &mut outcome),
+                Err(e) => vec![Mismatch::Body(format!("{}; data: {:?}", e, event.data))],
+            };
+            if !event_mismatches.is_empty() {
+                mismatches.push(Mismatch::Event {
+                    index: got,
+                    mismatches: event_mismatches,
+                });
+            }
+            outcomes.push(outcome);
+        }
+        got += 1;
+    }
+
+    if got != expected_count {
+        mismatches.push(Mismatch::EventCount {
+            expected: expected_count,
+            got,
+        });
+    }
+
+    if mismatches.is_empty() {
+        Ok(outcomes)
+    } else {
+        Err(TestFailure { mismatches })
+    }
+}
+
+/// An event of a Server-Sent Events stream.
+struct Event {
+    event: String,
+    data: String,
+}
+
+/// An incremental parser of a Server-Sent Events stream, which is fed the body as it arrives.
+#[derive(Default)]
+struct EventParser {
+    buf: Vec<u8>,
+    event: String,
+    data: Vec<String>,
+}
+
+impl EventParser {
+    fn push(&mut self, chunk: &[u8]) {
+        self.buf.extend_from_slice(chunk);
+    }
+
+    /// Returns the next complete event in the buffer. Comments, such as keep-alive lines, and
+    /// fields other than 'event' and 'data' are skipped.
+    fn next_event(&mut self) -> Option<Event> {
+        while let Some(pos) = self.buf.iter().position(|b| *b == b'\n') {
+            let mut line: Vec<u8> = self.buf.drain(..=pos).collect();
+            line.pop();
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+            let line = String::from_utf8_lossy(&line).into_owned();
+
+            if line.is_empty() {
+                if self.event.is_empty() && self.data.is_empty() {
+                    continue;
+                }
+                let event = std::mem::take(&mut self.event);
+                return Some(Event {
+                    event: if event.is_empty() { "message".to_string() } else { event },
+                    data: std::mem::take(&mut self.data).join("\n"),
+                });
+            }
+            if line.starts_with(':') {
+                continue;
+            }
+
+            let (field, value) = match line.split_once(':') {
+                Some((f, v)) => (f, v.strip_prefix(' ').unwrap_or(v)),
+                None => (line.as_str(), ""),
+            };
+            match field {
+                "event" => self.event = value.to_string(),
+                "data" => self.data.push(value.to_string()),
+                _ => {}
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EventParser;
+
+    fn events(body: &str) -> Vec<(String, String)> {
+        let mut parser = EventParser::default();
+        parser.push(body.as_bytes());
+        std::iter::from_fn(|| parser.next_event()).map(|e| (e.event, e.data)).collect()
+    }
+
+    #[test]
+    fn parser_accepts_crlf_line_endings() {
+        let got = events("event: next\r\ndata: {\"data\":1}\r\n\r\nevent: complete\r\n\r\n");
+        assert_eq!(got, [
+            ("next".to_string(), "{\"data\":1}".to_string()),
+            ("complete".to_string(), String::new()),
+        ]);
+    }
+
+    #[test]
+    fn parser_skips_keep_alive_comments() {
+        let got = events(":\n\n: ping\n\nevent: next\n: ping\ndata: 1\n\n");
+        assert_eq!(got, [("next".to_string(), "1".to_string())]);
+    }
+
+    #[test]
+    fn parser_joins_multi_line_data() {
+        let got = events("data: {\"data\":\ndata:  {\"a\":1}}\n\n");
+        assert_eq!(got, [("message".to_string(), "{\"data\":\n {\"a\":1}}".to_string())]);
+    }
+
+    #[test]
+    fn parser_waits_for_the_end_of_an_event() {
+        let mut parser = EventParser::default();
+        parser.push(b"event: next\r\ndata: 1\r");
+        assert!(parser.next_event().is_none());
+        parser.push(b"\n\r\n");
+        let event = parser.next_event().unwrap();
+        assert_eq!((event.event.as_str(), event.data.as_str()), ("next", "1"));
+    }
+}
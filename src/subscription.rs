@@ -0,0 +1,822 @@
+//! A driver for testing GraphQL subscriptions over a WebSocket with the `graphql-transport-ws`
+//! protocol or the legacy `subscriptions-transport-ws` protocol, against an actix_web `App` served
+//! on a local port. Available with the `subscriptions` feature.
+
+use std::time::Duration;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceFactory, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::{web, App, HttpServer};
+use futures_util::{SinkExt, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+use crate::check::check_graphql_value;
+use crate::{Argument, Expected, ExpectedError, GraphQLResponseError, Mismatch, TestFailure, TestOutcome};
+
+/// The id of the single operation subscribed to by the driver.
+const OPERATION_ID: &str = "1";
+
+/// The protocol spoken over the WebSocket of a subscription.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WsProtocol {
+    /// The `graphql-transport-ws` protocol of the `graphql-ws` library, with `subscribe`, `next`
+    /// and `complete` messages and `ping`/`pong` keep-alive. A server rejects a connection by
+    /// closing it, such as with code 4403.
+    #[default]
+    GraphQLTransportWs,
+    /// The legacy Apollo `subscriptions-transport-ws` protocol, with the `graphql-ws` subprotocol,
+    /// `start`, `data`, `stop` and `complete` messages and `ka` keep-alive. A server rejects a
+    /// connection with a `connection_error` message.
+    SubscriptionsTransportWs,
+}
+
+impl WsProtocol {
+    /// Returns the WebSocket subprotocol of the protocol.
+    pub fn subprotocol(&self) -> &'static str {
+        match self {
+            WsProtocol::GraphQLTransportWs => "graphql-transport-ws",
+            WsProtocol::SubscriptionsTransportWs => "graphql-ws",
+        }
+    }
+
+    fn subscribe(&self) -> &'static str {
+        match self {
+            WsProtocol::GraphQLTransportWs => "subscribe",
+            WsProtocol::SubscriptionsTransportWs => "start",
+        }
+    }
+
+    fn next(&self) -> &'static str {
+        match self {
+            WsProtocol::GraphQLTransportWs => "next",
+            WsProtocol::SubscriptionsTransportWs => "data",
+        }
+    }
+
+    fn stop(&self) -> &'static str {
+        match self {
+            WsProtocol::GraphQLTransportWs => "complete",
+            WsProtocol::SubscriptionsTransportWs => "stop",
+        }
+    }
+}
+
+/// A struct for defining the expected events of a subscription, which are compared in the same way
+/// for either [WsProtocol].
+///
+/// Each `next` or `data` message from the server is compared with the [Expected] in the same
+/// position, ignoring its status, headers and content type. An `error` message ends the
+/// subscription, and is compared as a GraphQL response with the errors of its payload and no data.
+/// Keep-alive messages are answered or skipped, and are not events.
+pub struct ExpectedEvents<V> {
+    /// A vector of the expected events, in the order the server sends them.
+    pub events: Vec<Expected<V>>,
+    /// If true, the server must end the subscription with `complete` after the events, and no
+    /// further events are allowed. If false, the driver ends the subscription itself once every
+    /// expected event has been received.
+    pub complete: bool,
+    /// If set, the server must reject the connection rather than acknowledge it, and `events` must
+    /// be empty. For [WsProtocol::SubscriptionsTransportWs] the payload of the `connection_error`
+    /// message is compared as a GraphQL error, or as the message of one if it is not an error
+    /// object. For [WsProtocol::GraphQLTransportWs] the reason of the close frame is compared as
+    /// the message, and the close code as the `code` extension, such as `"4403"`.
+    pub connection_error: Option<ExpectedError>,
+}
+
+impl<V> ExpectedEvents<V> {
+    /// Creates an expectation of the events, followed by `complete` from the server.
+    pub fn new(events: Vec<Expected<V>>) -> Self {
+        ExpectedEvents {
+            events,
+            complete: true,
+            connection_error: None,
+        }
+    }
+
+    /// Creates an expectation of the events, after which the driver ends the subscription.
+    pub fn first(events: Vec<Expected<V>>) -> Self {
+        ExpectedEvents {
+            events,
+            complete: false,
+            connection_error: None,
+        }
+    }
+
+    /// Creates an expectation that the server rejects the connection with the error.
+    pub fn rejected(error: ExpectedError) -> Self {
+        ExpectedEvents {
+            events: vec![],
+            complete: false,
+            connection_error: Some(error),
+        }
+    }
+}
+
+/// A driver that serves an actix_web `App` on a local port with the repository registered, and
+/// runs a subscription against it over a WebSocket.
+///
+/// As for an [crate::Executor], the repository of type `R` is registered with `App::app_data` as
+/// `web::Data<R>`. The headers of the [Argument] are sent with the WebSocket handshake, and its
+/// payload, which must be a JSON [crate::GraphQLRequest], is the payload of the `subscribe`
+/// message.
+#[derive(Debug, Clone)]
+pub struct SubscriptionDriver {
+    /// The path of the WebSocket endpoint. Defaults to `/graphql`.
+    pub path: String,
+    /// An optional payload of the `connection_init` message, such as authentication parameters.
+    pub connection_params: Option<Value>,
+    /// How long to wait for each message from the server. Defaults to 5 seconds.
+    pub timeout: Duration,
+    /// The protocol spoken over the WebSocket. Defaults to [WsProtocol::GraphQLTransportWs].
+    pub protocol: WsProtocol,
+}
+
+impl Default for SubscriptionDriver {
+    fn default() -> Self {
+        SubscriptionDriver {
+            path: "/graphql".to_string(),
+            connection_params: None,
+            timeout: Duration::from_secs(5),
+            protocol: WsProtocol::GraphQLTransportWs,
+        }
+    }
+}
+
+impl SubscriptionDriver {
+    /// Creates a driver for the endpoint at the path.
+    pub fn new(path: &str) -> Self {
+        SubscriptionDriver {
+            path: path.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Sets the payload of the `connection_init` message from any serializable type. Panics if
+    /// the parameters fail to serialize.
+    pub fn with_connection_params<T: Serialize>(mut self, params: T) -> Self {
+        match serde_json::to_value(params) {
+            Ok(v) => self.connection_params = Some(v),
+            Err(e) => panic!("Failed to serialize connection params: {}", e),
+        }
+        self
+    }
+
+    /// Sets how long to wait for each message from the server.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the protocol spoken over the WebSocket.
+    pub fn with_protocol(mut self, protocol: WsProtocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Runs the subscription, and panics with a list of every mismatch if any are found. See
+    /// [SubscriptionDriver::try_subscribe].
+    pub async fn subscribe<R, F, T, B, V>(&self, app_factory: F, repo: R, arg: Argument, exp: ExpectedEvents<V>)
+    where
+        R: Clone + Send + 'static,
+        F: Fn() -> App<T> + Send + Clone + 'static,
+        T: ServiceFactory<
+                ServiceRequest,
+                Config = (),
+                Response = ServiceResponse<B>,
+                Error = actix_web::Error,
+                InitError = (),
+            > + 'static,
+        B: MessageBody + 'static,
+        V: DeserializeOwned + Serialize + PartialEq + std::fmt::Debug,
+    {
+        if let Err(failure) = self.try_subscribe(app_factory, repo, arg, exp).await {
+            panic!("{}", failure);
+        }
+    }
+
+    /// Serves the app returned by `app_factory` on a local port with the repository registered,
+    /// connects to it, subscribes with the argument, and compares the events of the subscription
+    /// to the expected events. Must be called within an actix runtime, such as an
+    /// `#[actix_web::test]`. Returns a [TestOutcome] for every event if each matched, with the
+    /// status and headers of the handshake response, or a [TestFailure] listing every mismatch.
+    pub async fn try_subscribe<R, F, T, B, V>(
+        &self,
+        app_factory: F,
+        repo: R,
+        arg: Argument,
+        exp: ExpectedEvents<V>,
+    ) -> Result<Vec<TestOutcome<V>>, TestFailure>
+    where
+        R: Clone + Send + 'static,
+        F: Fn() -> App<T> + Send + Clone + 'static,
+        T: ServiceFactory<
+                ServiceRequest,
+                Config = (),
+                Response = ServiceResponse<B>,
+                Error = actix_web::Error,
+                InitError = (),
+            > + 'static,
+        B: MessageBody + 'static,
+        V: DeserializeOwned + Serialize + PartialEq + std::fmt::Debug,
+    {
+        let server = match HttpServer::new(move || app_factory().app_data(web::Data::new(repo.clone())))
+            .workers(1)
+            .disable_signals()
+            .bind(("127.0.0.1", 0))
+        {
+            Ok(s) => s,
+            Err(e) => panic!("Failed to bind a local port for the subscription server: {}", e),
+        };
+        let addr = server.addrs()[0];
+        let server = server.run();
+        let handle = server.handle();
+        actix_web::rt::spawn(server);
+
+        let result = self.run(addr, arg, exp).await;
+
+        handle.stop(false).await;
+        result
+    }
+
+    async fn run<V>(
+        &self,
+        addr: std::net::SocketAddr,
+        arg: Argument,
+        exp: ExpectedEvents<V>,
+    ) -> Result<Vec<TestOutcome<V>>, TestFailure>
+    where
+        V: DeserializeOwned + Serialize + PartialEq + std::fmt::Debug,
+    {
+        let fail = |m: Mismatch| TestFailure { mismatches: vec![m] };
+
+        let payload: Value = match serde_json::from_str(&arg.payload) {
+            Ok(p) => p,
+            Err(e) => panic!("Subscriptions require a JSON GraphQL request payload: {}", e),
+        };
+
+        let (mut ws, headers) = self.connect(addr, &arg).await.map_err(fail)?;
+
+        let mut init = json!({"type": "connection_init"});
+        if let Some(params) = &self.connection_params {
+            init["payload"] = params.clone();
+        }
+        send(&mut ws, init).await.map_err(fail)?;
+
+        let rejection = match self.receive_frame(&mut ws).await.map_err(fail)? {
+            Incoming::Message(m) if m["type"] == "connection_ack" => None,
+            Incoming::Message(m) if m["type"] == "connection_error" => Some(match m.get("payload") {
+                Some(p) => serde_json::from_value(p.clone()).unwrap_or_else(|_| match p {
+                    Value::String(message) => error_with_message(message.clone()),
+                    p => error_with_message(p.to_string()),
+                }),
+                None => error_with_message(String::new()),
+            }),
+            Incoming::Closed(Some((code, reason))) if self.protocol == WsProtocol::GraphQLTransportWs => {
+                let mut error = error_with_message(reason);
+                error.extensions = Some(serde_json::Map::from_iter([("code".to_string(), json!(code.to_string()))]));
+                Some(error)
+            }
+            Incoming::Message(m) => {
+                return Err(fail(Mismatch::Protocol(format!("expected connection_ack, got {}", m))))
+            }
+            Incoming::Closed(frame) => return Err(fail(closed(frame))),
+        };
+
+        match (rejection, &exp.connection_error) {
+            (None, None) => {}
+            (Some(got), Some(exp_error)) => {
+                self.close(&mut ws).await;
+                let details = exp_error.mismatches(&got);
+                return if details.is_empty() {
+                    Ok(vec![])
+                } else {
+                    Err(fail(Mismatch::Error { index: 0, details }))
+                };
+            }
+            (Some(got), None) => {
+                self.close(&mut ws).await;
+                let extensions = got.extensions.map(|e| format!(", extensions {}", Value::Object(e)));
+                return Err(fail(Mismatch::Protocol(format!(
+                    "connection rejected with message {:?}{}",
+                    got.message,
+                    extensions.unwrap_or_default()
+                ))));
+            }
+            (None, Some(_)) => {
+                self.close(&mut ws).await;
+                return Err(fail(Mismatch::Protocol(
+                    "expected the connection to be rejected, got connection_ack".to_string(),
+                )));
+            }
+        }
+
+        send(&mut ws, json!({"id": OPERATION_ID, "type": self.protocol.subscribe(), "payload": payload}))
+            .await
+            .map_err(fail)?;
+
+        let mut mismatches = vec![];
+        let mut outcomes = vec![];
+        let expected_count = exp.events.len();
+        let mut expected = exp.events.into_iter();
+        let mut got = 0;
+        let mut ended = false;
+
+        while exp.complete || got < expected_count {
+            let message = match self.receive(&mut ws).await {
+                Ok(m) => m,
+                Err(m) => {
+                    mismatches.push(m);
+                    break;
+                }
+            };
+
+            let event = match message["type"].as_str() {
+                Some(t) if t == self.protocol.next() => message["payload"].clone(),
+                Some("error") => {
+                    ended = true;
+                    match &message["payload"] {
+                        Value::Array(errors) => json!({ "errors": errors }),
+                        error => json!({ "errors": [error] }),
+                    }
+                }
+                Some("complete") => {
+                    ended = true;
+                    break;
+                }
+                _ => {
+                    mismatches.push(Mismatch::Protocol(format!("unexpected message {}", message)));
+                    break;
+                }
+            };
+
+            if let Some(event_exp) = expected.next() {
+                let mut outcome = TestOutcome {
+                    status: StatusCode::SWITCHING_PROTOCOLS,
+                    headers: headers.clone(),
+                    content_type: None,
+                    data: None,
+                    errors: vec![],
+                    body: None,
+                };
+                let event_mismatches = check_graphql_value(event, event_exp, &mut outcome);
+                if !event_mismatches.is_empty() {
+                    mismatches.push(Mismatch::Event {
+                        index: got,
+                        mismatches: event_mismatches,
+                    });
+                }
+                outcomes.push(outcome);
+            }
+            got += 1;
+
+            if ended {
+                break;
+            }
+        }
+
+        if got != expected_count {
+            mismatches.push(Mismatch::EventCount {
+                expected: expected_count,
+                got,
+            });
+        }
+
+        if !ended {
+            let _ = send(&mut ws, json!({"id": OPERATION_ID, "type": self.protocol.stop()})).await;
+        }
+        if self.protocol == WsProtocol::SubscriptionsTransportWs {
+            let _ = send(&mut ws, json!({"type": "connection_terminate"})).await;
+        }
+        self.close(&mut ws).await;
+
+        if mismatches.is_empty() {
+            Ok(outcomes)
+        } else {
+            Err(TestFailure { mismatches })
+        }
+    }
+
+    /// Opens a WebSocket to the server with the subprotocol of the protocol and the headers of the
+    /// argument. Returns the socket and the headers of the handshake response.
+    async fn connect(
+        &self,
+        addr: std::net::SocketAddr,
+        arg: &Argument,
+    ) -> Result<(WebSocketStream<TcpStream>, HeaderMap), Mismatch> {
+        let url = format!("ws://{}{}", addr, self.path);
+        let mut request = match url.as_str().into_client_request() {
+            Ok(r) => r,
+            Err(e) => panic!("Invalid subscription path {:?}: {}", self.path, e),
+        };
+        request.headers_mut().insert(
+            "Sec-WebSocket-Protocol",
+            self.protocol.subprotocol().parse().expect("subprotocol is a valid header value"),
+        );
+        for (name, value) in &arg.headers {
+            if name.eq_ignore_ascii_case("content-type") {
+                continue;
+            }
+            match (name.parse::<tokio_tungstenite::tungstenite::http::HeaderName>(), value.parse()) {
+                (Ok(n), Ok(v)) => {
+                    request.headers_mut().append(n, v);
+                }
+                _ => panic!("Invalid header {:?}: {:?}", name, value),
+            }
+        }
+
+        let stream = TcpStream::connect(addr)
+            .await
+            .map_err(|e| Mismatch::Protocol(format!("failed to connect: {}", e)))?;
+        let (ws, response) = tokio_tungstenite::client_async(request, stream)
+            .await
+            .map_err(|e| Mismatch::Protocol(format!("WebSocket handshake failed: {}", e)))?;
+
+        let mut headers = HeaderMap::new();
+        for (name, value) in response.headers() {
+            if let (Ok(n), Ok(v)) = (
+                HeaderName::from_bytes(name.as_str().as_bytes()),
+                HeaderValue::from_bytes(value.as_bytes()),
+            ) {
+                headers.append(n, v);
+            }
+        }
+        Ok((ws, headers))
+    }
+
+    /// Closes the WebSocket, and waits for the close frame of the server in reply, so that the
+    /// server has read every message sent before.
+    async fn close(&self, ws: &mut WebSocketStream<TcpStream>) {
+        // a socket closed by the server has already had its reply
+        if ws.close(None).await.is_err() {
+            return;
+        }
+        let reply = async {
+            while let Some(Ok(message)) = ws.next().await {
+                if message.is_close() {
+                    break;
+                }
+            }
+        };
+        let _ = tokio::time::timeout(self.timeout, reply).await;
+    }
+
+    /// Waits for the next protocol message from the server, failing if the connection closes.
+    async fn receive(&self, ws: &mut WebSocketStream<TcpStream>) -> Result<Value, Mismatch> {
+        match self.receive_frame(ws).await? {
+            Incoming::Message(m) => Ok(m),
+            Incoming::Closed(frame) => Err(closed(frame)),
+        }
+    }
+
+    /// Waits for the next protocol message from the server or the close of the connection. 
+    /// Keep-alive messages are skipped, and a `ping` is answered with a `pong`.
+    async fn receive_frame(&self, ws: &mut WebSocketStream<TcpStream>) -> Result<Incoming, Mismatch> {
+        loop {
+            let frame = match tokio::time::timeout(self.timeout, ws.next()).await {
+                Ok(f) => f,
+                Err(_) => return Err(Mismatch::Timeout { after: self.timeout }),
+            };
+            let text = match frame {
+                Some(Ok(Message::Text(t))) => t,
+                Some(Ok(Message::Close(frame))) => {
+                    return Ok(Incoming::Closed(frame.map(|f| (u16::from(f.code), f.reason.to_string()))))
+                }
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => return Err(Mismatch::Protocol(format!("WebSocket error: {}", e))),
+                None => return Ok(Incoming::Closed(None)),
+            };
+
+            let message: Value = serde_json::from_str(text.as_str())
+                .map_err(|e| Mismatch::Protocol(format!("invalid message {:?}: {}", text.as_str(), e)))?;
+            match (self.protocol, message["type"].as_str()) {
+                (WsProtocol::GraphQLTransportWs, Some("ping")) => send(ws, json!({"type": "pong"})).await?,
+                (WsProtocol::GraphQLTransportWs, Some("pong")) => {}
+                (WsProtocol::SubscriptionsTransportWs, Some("ka")) => {}
+                _ => return Ok(Incoming::Message(message)),
+            }
+        }
+    }
+}
+
+/// A protocol message from the server, or the close of the connection with an optional code and
+/// reason.
+enum Incoming {
+    Message(Value),
+    Closed(Option<(u16, String)>),
+}
+
+fn closed(frame: Option<(u16, String)>) -> Mismatch {
+    Mismatch::Protocol(match frame {
+        Some((code, reason)) => format!("connection closed with code {}: {}", code, reason),
+        None => "connection closed".to_string(),
+    })
+}
+
+fn error_with_message(message: String) -> GraphQLResponseError {
+    GraphQLResponseError {
+        message,
+        locations: None,
+        path: None,
+        extensions: None,
+    }
+}
+
+async fn send(ws: &mut WebSocketStream<TcpStream>, message: Value) -> Result<(), Mismatch> {
+    ws.send(Message::text(message.to_string()))
+        .await
+        .map_err(|e| Mismatch::Protocol(format!("failed to send message: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use actix_web::http::header::SEC_WEBSOCKET_PROTOCOL;
+    use actix_web::{HttpRequest, HttpResponse};
+    use actix_ws::{CloseCode, CloseReason, MessageStream, Session};
+
+    use super::*;
+    use crate::GraphQLRequest;
+
+    /// The messages received by the test server, and the address it was served on.
+    type Log = Arc<Mutex<Vec<String>>>;
+
+    /// A server of either protocol, which sends the number of events given by the `events`
+    /// connection parameter, followed by `complete` if the `complete` parameter is true. If the
+    /// `reject` parameter is set, the server rejects the connection instead: with a close code of
+    /// 4403 for `graphql-transport-ws`, and with the parameter as the payload of a
+    /// `connection_error` message for `graphql-ws`.
+    async fn graphql_ws(req: HttpRequest, body: web::Payload, log: web::Data<Log>) -> actix_web::Result<HttpResponse> {
+        let protocol = match req.headers().get(SEC_WEBSOCKET_PROTOCOL).and_then(|v| v.to_str().ok()) {
+            Some("graphql-transport-ws") => WsProtocol::GraphQLTransportWs,
+            Some("graphql-ws") => WsProtocol::SubscriptionsTransportWs,
+            _ => return Ok(HttpResponse::BadRequest().finish()),
+        };
+        let (mut response, session, messages) = actix_ws::handle(&req, body)?;
+        response
+            .headers_mut()
+            .insert(SEC_WEBSOCKET_PROTOCOL, HeaderValue::from_static(protocol.subprotocol()));
+        log.lock().unwrap().push(format!("addr {}", req.app_config().local_addr()));
+        actix_web::rt::spawn(serve(protocol, session, messages, log.get_ref().clone()));
+        Ok(response)
+    }
+
+    async fn serve(protocol: WsProtocol, mut session: Session, mut messages: MessageStream, log: Log) {
+        let legacy = protocol == WsProtocol::SubscriptionsTransportWs;
+        let Some(init) = receive(&mut messages, &mut session, &log).await else {
+            return;
+        };
+        let params = &init["payload"];
+
+        match &params["reject"] {
+            Value::Null => {}
+            _ if !legacy => {
+                let reason = CloseReason {
+                    code: CloseCode::Other(4403),
+                    description: Some("Forbidden".to_string()),
+                };
+                let _ = session.close(Some(reason)).await;
+                return;
+            }
+            payload => {
+                let _ = session.text(json!({"type": "connection_error", "payload": payload}).to_string()).await;
+                let _ = session.close(None).await;
+                return;
+            }
+        }
+
+        let _ = session.text(json!({"type": "connection_ack"}).to_string()).await;
+        let Some(subscribe) = receive(&mut messages, &mut session, &log).await else {
+            return;
+        };
+
+        if legacy {
+            let _ = session.text(json!({"type": "ka"}).to_string()).await;
+        } else {
+            let _ = session.text(json!({"type": "ping"}).to_string()).await;
+            if receive(&mut messages, &mut session, &log).await.map(|m| m["type"] == "pong") != Some(true) {
+                return;
+            }
+        }
+
+        let id = &subscribe["id"];
+        for count in 0..params["events"].as_u64().unwrap_or_default() {
+            let next = json!({"id": id, "type": protocol.next(), "payload": {"data": {"count": count}}});
+            let _ = session.text(next.to_string()).await;
+            if legacy {
+                let _ = session.text(json!({"type": "ka"}).to_string()).await;
+            }
+        }
+        if params["complete"] == true {
+            let _ = session.text(json!({"id": id, "type": "complete"}).to_string()).await;
+        }
+
+        while receive(&mut messages, &mut session, &log).await.is_some() {}
+    }
+
+    /// Returns the next message from the client, logging its type, or None once the client closes
+    /// the connection.
+    async fn receive(messages: &mut MessageStream, session: &mut Session, log: &Log) -> Option<Value> {
+        loop {
+            match messages.recv().await? {
+                Ok(actix_ws::Message::Text(text)) => {
+                    let message: Value = serde_json::from_str(&text).unwrap();
+                    log.lock().unwrap().push(message["type"].as_str().unwrap_or_default().to_string());
+                    return Some(message);
+                }
+                Ok(actix_ws::Message::Close(reason)) => {
+                    let _ = session.clone().close(reason).await;
+                    return None;
+                }
+                Ok(_) => {}
+                Err(_) => return None,
+            }
+        }
+    }
+
+    fn app() -> App<
+        impl ServiceFactory<
+            ServiceRequest,
+            Config = (),
+            Response = ServiceResponse<impl MessageBody>,
+            Error = actix_web::Error,
+            InitError = (),
+        >,
+    > {
+        App::new().route("/graphql", web::get().to(graphql_ws))
+    }
+
+    fn counts(counts: std::ops::Range<u64>) -> Vec<Expected<Value>> {
+        counts
+            .map(|count| Expected {
+                data: Some(json!({"count": count})),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    type Subscribed = (Result<Vec<TestOutcome<Value>>, TestFailure>, Vec<String>);
+
+    async fn subscribe(params: Value, exp: ExpectedEvents<Value>) -> Subscribed {
+        subscribe_with(WsProtocol::GraphQLTransportWs, params, exp).await
+    }
+
+    async fn subscribe_with(protocol: WsProtocol, params: Value, exp: ExpectedEvents<Value>) -> Subscribed {
+        let log = Log::default();
+        let driver = SubscriptionDriver::default()
+            .with_protocol(protocol)
+            .with_connection_params(params)
+            .with_timeout(Duration::from_secs(2));
+        let arg = Argument::new(&GraphQLRequest::new("subscription { count }"));
+        let result = driver.try_subscribe(app, log.clone(), arg, exp).await;
+        let log = log.lock().unwrap().clone();
+        (result, log)
+    }
+
+    fn event_count(result: Result<Vec<TestOutcome<Value>>, TestFailure>) -> Option<(usize, usize)> {
+        result.err()?.mismatches.iter().find_map(|m| match m {
+            Mismatch::EventCount { expected, got } => Some((*expected, *got)),
+            _ => None,
+        })
+    }
+
+    #[actix_web::test]
+    async fn receives_every_event_until_complete() {
+        let (result, log) = subscribe(json!({"events": 2, "complete": true}), ExpectedEvents::new(counts(0..2))).await;
+        let outcomes = result.unwrap();
+        assert_eq!(outcomes.len(), 2);
+        assert_eq!(outcomes[1].status, StatusCode::SWITCHING_PROTOCOLS);
+        assert_eq!(outcomes[1].headers.get(SEC_WEBSOCKET_PROTOCOL).unwrap(), "graphql-transport-ws");
+        assert_eq!(log[1..], ["connection_init", "subscribe", "pong"]);
+
+        // the server has stopped
+        let addr = log[0].strip_prefix("addr ").unwrap();
+        assert!(std::net::TcpStream::connect(addr).is_err());
+    }
+
+    #[actix_web::test]
+    async fn completes_the_subscription_after_the_first_events() {
+        let (result, log) = subscribe(json!({"events": 3}), ExpectedEvents::first(counts(0..2))).await;
+        assert_eq!(result.unwrap().len(), 2);
+        assert_eq!(log[1..], ["connection_init", "subscribe", "pong", "complete"]);
+    }
+
+    #[actix_web::test]
+    async fn reports_too_few_events() {
+        let (result, _) = subscribe(json!({"events": 1, "complete": true}), ExpectedEvents::new(counts(0..2))).await;
+        assert_eq!(event_count(result), Some((2, 1)));
+    }
+
+    #[actix_web::test]
+    async fn reports_too_many_events() {
+        let (result, _) = subscribe(json!({"events": 3, "complete": true}), ExpectedEvents::new(counts(0..2))).await;
+        assert_eq!(event_count(result), Some((2, 3)));
+    }
+
+    #[actix_web::test]
+    async fn reports_a_missing_complete_as_a_timeout() {
+        let driver = SubscriptionDriver::default()
+            .with_connection_params(json!({"events": 1}))
+            .with_timeout(Duration::from_millis(200));
+        let arg = Argument::new(&GraphQLRequest::new("subscription { count }"));
+        let failure = driver
+            .try_subscribe(app, Log::default(), arg, ExpectedEvents::new(counts(0..1)))
+            .await
+            .unwrap_err();
+        assert!(matches!(failure.mismatches[..], [Mismatch::Timeout { .. }]));
+    }
+
+    fn protocol_failure(result: Result<Vec<TestOutcome<Value>>, TestFailure>) -> String {
+        match &result.unwrap_err().mismatches[..] {
+            [Mismatch::Protocol(s)] => s.clone(),
+            other => panic!("expected a protocol failure, got {:?}", other),
+        }
+    }
+
+    #[actix_web::test]
+    async fn legacy_protocol_skips_keep_alive_and_terminates() {
+        let legacy = WsProtocol::SubscriptionsTransportWs;
+        let exp = ExpectedEvents::new(counts(0..2));
+        let (result, log) = subscribe_with(legacy, json!({"events": 2, "complete": true}), exp).await;
+        let outcomes = result.unwrap();
+        assert_eq!(outcomes[0].headers.get(SEC_WEBSOCKET_PROTOCOL).unwrap(), "graphql-ws");
+        assert_eq!(log[1..], ["connection_init", "start", "connection_terminate"]);
+    }
+
+    #[actix_web::test]
+    async fn legacy_protocol_stops_after_the_first_events() {
+        let legacy = WsProtocol::SubscriptionsTransportWs;
+        let (result, log) = subscribe_with(legacy, json!({"events": 3}), ExpectedEvents::first(counts(0..2))).await;
+        assert_eq!(result.unwrap().len(), 2);
+        assert_eq!(log[1..], ["connection_init", "start", "stop", "connection_terminate"]);
+    }
+
+    #[actix_web::test]
+    async fn legacy_protocol_compares_a_connection_error() {
+        let legacy = WsProtocol::SubscriptionsTransportWs;
+        let params = json!({"reject": {"message": "denied", "extensions": {"code": "FORBIDDEN"}}});
+        let exp = ExpectedEvents::rejected(ExpectedError::message("denied").with_code("FORBIDDEN"));
+        let (result, _) = subscribe_with(legacy, params, exp).await;
+        assert!(result.unwrap().is_empty());
+
+        // a payload that is not an error object is compared as the message
+        let exp = ExpectedEvents::rejected(ExpectedError::message("denied"));
+        let (result, _) = subscribe_with(legacy, json!({"reject": "denied"}), exp).await;
+        assert!(result.unwrap().is_empty());
+
+        let exp = ExpectedEvents::rejected(ExpectedError::message(r#"["denied"]"#));
+        let (result, _) = subscribe_with(legacy, json!({"reject": ["denied"]}), exp).await;
+        assert!(result.unwrap().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn compares_a_close_code_and_reason_as_an_error() {
+        let exp = ExpectedEvents::rejected(ExpectedError::message("Forbidden").with_code("4403"));
+        let (result, log) = subscribe(json!({"reject": true}), exp).await;
+        assert!(result.unwrap().is_empty());
+        assert_eq!(log[1..], ["connection_init"]);
+
+        let exp = ExpectedEvents::rejected(ExpectedError::code("4401"));
+        let (result, _) = subscribe(json!({"reject": true}), exp).await;
+        match &result.unwrap_err().mismatches[..] {
+            [Mismatch::Error { index: 0, details }] => {
+                assert_eq!(details, &[r#"extensions.code: expected "4401", got Some("4403")"#])
+            }
+            other => panic!("expected an error mismatch, got {:?}", other),
+        }
+    }
+
+    #[actix_web::test]
+    async fn reports_an_unexpected_rejection() {
+        let (result, _) = subscribe(json!({"reject": true}), ExpectedEvents::new(counts(0..1))).await;
+        assert_eq!(
+            protocol_failure(result),
+            r#"connection rejected with message "Forbidden", extensions {"code":"4403"}"#
+        );
+
+        let legacy = WsProtocol::SubscriptionsTransportWs;
+        let params = json!({"reject": {"message": "denied"}});
+        let (result, _) = subscribe_with(legacy, params, ExpectedEvents::new(vec![])).await;
+        assert_eq!(protocol_failure(result), r#"connection rejected with message "denied""#);
+    }
+
+    #[actix_web::test]
+    async fn reports_an_acknowledged_connection_that_should_be_rejected() {
+        for protocol in [WsProtocol::GraphQLTransportWs, WsProtocol::SubscriptionsTransportWs] {
+            let exp = ExpectedEvents::rejected(ExpectedError::any());
+            let (result, _) = subscribe_with(protocol, json!({}), exp).await;
+            assert_eq!(
+                protocol_failure(result),
+                "expected the connection to be rejected, got connection_ack"
+            );
+        }
+    }
+}